@@ -0,0 +1,77 @@
+use auditor::{index, parser, search};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+const DEFAULT_ROWS: usize = 1_000_000;
+
+const PROGRAMS: &[&str] = &[
+    "/usr/bin/git",
+    "/bin/ls",
+    "/usr/bin/curl",
+    "/usr/bin/ssh",
+    "/run/current-system/sw/bin/starship",
+    "/usr/bin/python3",
+    "/bin/bash",
+    "/usr/bin/systemctl",
+];
+
+const ARGS: &[&str] = &[
+    "status",
+    "-la",
+    "--right",
+    "https://example.com/install.sh",
+    "prompt",
+    "restart nginx",
+    "-c 'echo hello'",
+    "script.py --verbose",
+];
+
+fn generate_logs(rows: usize) -> Vec<parser::AuditLogResponse> {
+    (0..rows)
+        .map(|i| parser::AuditLogResponse {
+            id: i as i64 + 1,
+            timestamp: format!("2024-05-29T17:{:02}:{:02}.{:09}Z", i / 60 % 60, i % 60, i),
+            command: format!(
+                "{} {} {}",
+                PROGRAMS[i % PROGRAMS.len()],
+                ARGS[i / PROGRAMS.len() % ARGS.len()],
+                i % 997
+            ),
+            key: None,
+            success: None,
+        })
+        .rev()
+        .collect()
+}
+
+fn bench_search(c: &mut Criterion) {
+    let rows = std::env::var("AUDITOR_BENCH_ROWS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_ROWS);
+
+    let audit_logs = generate_logs(rows);
+
+    let mut search_index = index::SearchIndex::default();
+    for audit_log in generate_logs(rows) {
+        search_index.insert(audit_log);
+    }
+
+    let options = search::SearchOptions::default();
+
+    let mut group = c.benchmark_group(format!("search_{rows}_rows"));
+    group.sample_size(10);
+
+    for query in ["curl install", "starship prompt"] {
+        group.bench_function(format!("fuzzy_search_best_n/{query}"), |b| {
+            b.iter(|| search::fuzzy_search_best_n(black_box(query), &audit_logs, 20, &options))
+        });
+        group.bench_function(format!("search_index/{query}"), |b| {
+            b.iter(|| search_index.search(black_box(query), 20, &options))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_search);
+criterion_main!(benches);
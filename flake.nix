@@ -23,10 +23,11 @@
 
       htmlFilter = path: _type: null != builtins.match ".*html$" path;
       sqlFilter = path: _type: null != builtins.match ".*sql$" path;
-      htmlOrCargoOrSql = path: type: (htmlFilter path type) || (sqlFilter path type) || (craneLib.filterCargoSources path type);
+      ymlFilter = path: _type: null != builtins.match ".*yml$" path;
+      htmlOrCargoOrSqlOrYml = path: type: (htmlFilter path type) || (sqlFilter path type) || (ymlFilter path type) || (craneLib.filterCargoSources path type);
       src = lib.cleanSourceWith {
         src = craneLib.path ./.;
-        filter = htmlOrCargoOrSql;
+        filter = htmlOrCargoOrSqlOrYml;
       };
 
       auditor = craneLib.buildPackage {
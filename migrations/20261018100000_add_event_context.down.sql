@@ -0,0 +1,8 @@
+-- Add down migration script here
+DROP INDEX idx_audit_log_timestamp;
+
+ALTER TABLE audit_log DROP COLUMN user;
+ALTER TABLE audit_log DROP COLUMN uid;
+ALTER TABLE audit_log DROP COLUMN pid;
+ALTER TABLE audit_log DROP COLUMN host;
+ALTER TABLE audit_log DROP COLUMN serial;
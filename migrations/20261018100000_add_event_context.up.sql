@@ -0,0 +1,8 @@
+-- Add up migration script here
+ALTER TABLE audit_log ADD COLUMN serial INTEGER;
+ALTER TABLE audit_log ADD COLUMN host TEXT;
+ALTER TABLE audit_log ADD COLUMN pid INTEGER;
+ALTER TABLE audit_log ADD COLUMN uid INTEGER;
+ALTER TABLE audit_log ADD COLUMN user TEXT;
+
+CREATE INDEX idx_audit_log_timestamp ON audit_log (timestamp, id);
@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE audit_log ADD COLUMN raw TEXT;
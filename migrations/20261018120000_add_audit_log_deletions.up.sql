@@ -0,0 +1,9 @@
+-- Add up migration script here
+CREATE TABLE audit_log_deletion (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    deleted_at TEXT NOT NULL,
+    actor TEXT,
+    remote_addr TEXT,
+    filters TEXT NOT NULL,
+    count INTEGER NOT NULL
+);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP TABLE session;
+DROP TABLE api_token;
+DROP TABLE user;
@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP INDEX idx_audit_log_ppid;
+DROP INDEX idx_audit_log_pid;
+ALTER TABLE audit_log DROP COLUMN ppid;
@@ -0,0 +1,5 @@
+-- Add up migration script here
+ALTER TABLE audit_log ADD COLUMN ppid INTEGER;
+
+CREATE INDEX idx_audit_log_pid ON audit_log (host, pid, timestamp);
+CREATE INDEX idx_audit_log_ppid ON audit_log (host, ppid, timestamp);
@@ -0,0 +1,6 @@
+-- Add down migration script here
+DROP INDEX idx_audit_log_ses;
+ALTER TABLE audit_log DROP COLUMN login_user;
+ALTER TABLE audit_log DROP COLUMN auid;
+ALTER TABLE audit_log DROP COLUMN tty;
+ALTER TABLE audit_log DROP COLUMN ses;
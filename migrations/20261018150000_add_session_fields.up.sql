@@ -0,0 +1,7 @@
+-- Add up migration script here
+ALTER TABLE audit_log ADD COLUMN ses INTEGER;
+ALTER TABLE audit_log ADD COLUMN tty TEXT;
+ALTER TABLE audit_log ADD COLUMN auid INTEGER;
+ALTER TABLE audit_log ADD COLUMN login_user TEXT;
+
+CREATE INDEX idx_audit_log_ses ON audit_log (host, ses, timestamp);
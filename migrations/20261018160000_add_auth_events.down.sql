@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP INDEX idx_auth_event_ses;
+DROP INDEX idx_auth_event_timestamp;
+DROP TABLE auth_event;
@@ -0,0 +1,24 @@
+-- Add up migration script here
+CREATE TABLE auth_event (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    record_type TEXT NOT NULL,
+    timestamp TEXT NOT NULL,
+    serial INTEGER NOT NULL,
+    host TEXT,
+    pid INTEGER,
+    uid INTEGER,
+    auid INTEGER,
+    ses INTEGER,
+    account TEXT,
+    op TEXT,
+    exe TEXT,
+    hostname TEXT,
+    addr TEXT,
+    terminal TEXT,
+    success BOOLEAN NOT NULL,
+    raw TEXT NOT NULL,
+    UNIQUE (host, timestamp, serial, record_type)
+);
+
+CREATE INDEX idx_auth_event_timestamp ON auth_event (timestamp);
+CREATE INDEX idx_auth_event_ses ON auth_event (host, ses);
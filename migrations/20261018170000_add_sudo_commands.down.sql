@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP INDEX idx_sudo_command_audit_log;
+DROP INDEX idx_sudo_command_timestamp;
+DROP TABLE sudo_command;
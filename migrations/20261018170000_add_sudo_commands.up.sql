@@ -0,0 +1,23 @@
+-- Add up migration script here
+CREATE TABLE sudo_command (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    timestamp TEXT NOT NULL,
+    serial INTEGER NOT NULL,
+    host TEXT,
+    pid INTEGER,
+    uid INTEGER,
+    auid INTEGER,
+    ses INTEGER,
+    login_user TEXT,
+    cwd TEXT,
+    command TEXT NOT NULL,
+    program TEXT NOT NULL,
+    terminal TEXT,
+    success BOOLEAN NOT NULL,
+    raw TEXT NOT NULL,
+    audit_log_id INTEGER REFERENCES audit_log (id) ON DELETE SET NULL,
+    UNIQUE (host, timestamp, serial)
+);
+
+CREATE INDEX idx_sudo_command_timestamp ON sudo_command (timestamp);
+CREATE INDEX idx_sudo_command_audit_log ON sudo_command (audit_log_id);
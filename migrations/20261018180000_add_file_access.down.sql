@@ -0,0 +1,5 @@
+-- Add down migration script here
+DROP INDEX idx_file_access_path;
+DROP INDEX idx_file_access_key;
+DROP INDEX idx_file_access_timestamp;
+DROP TABLE file_access;
@@ -0,0 +1,29 @@
+-- Add up migration script here
+CREATE TABLE file_access (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    timestamp TEXT NOT NULL,
+    serial INTEGER NOT NULL,
+    item INTEGER NOT NULL,
+    host TEXT,
+    pid INTEGER,
+    ppid INTEGER,
+    uid INTEGER,
+    user TEXT,
+    auid INTEGER,
+    login_user TEXT,
+    ses INTEGER,
+    exe TEXT,
+    syscall TEXT,
+    path TEXT NOT NULL,
+    nametype TEXT,
+    operation TEXT NOT NULL,
+    success BOOLEAN,
+    exit INTEGER,
+    key TEXT,
+    raw TEXT NOT NULL,
+    UNIQUE (host, timestamp, serial, item)
+);
+
+CREATE INDEX idx_file_access_timestamp ON file_access (timestamp);
+CREATE INDEX idx_file_access_key ON file_access (key, timestamp);
+CREATE INDEX idx_file_access_path ON file_access (path);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP INDEX idx_audit_log_key;
+ALTER TABLE audit_log DROP COLUMN key;
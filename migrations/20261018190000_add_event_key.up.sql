@@ -0,0 +1,4 @@
+-- Add up migration script here
+ALTER TABLE audit_log ADD COLUMN key TEXT;
+
+CREATE INDEX idx_audit_log_key ON audit_log (key, timestamp);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP INDEX idx_audit_log_success;
+ALTER TABLE audit_log DROP COLUMN exit;
+ALTER TABLE audit_log DROP COLUMN success;
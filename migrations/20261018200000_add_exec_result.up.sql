@@ -0,0 +1,5 @@
+-- Add up migration script here
+ALTER TABLE audit_log ADD COLUMN success BOOLEAN;
+ALTER TABLE audit_log ADD COLUMN exit INTEGER;
+
+CREATE INDEX idx_audit_log_success ON audit_log (success, timestamp);
@@ -0,0 +1,5 @@
+-- Add down migration script here
+DROP INDEX idx_alert_audit_log;
+DROP INDEX idx_alert_rule;
+DROP INDEX idx_alert_timestamp;
+DROP TABLE alert;
@@ -0,0 +1,17 @@
+-- Add up migration script here
+CREATE TABLE alert (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    rule_id TEXT NOT NULL,
+    title TEXT NOT NULL,
+    severity TEXT NOT NULL,
+    audit_log_id INTEGER REFERENCES audit_log (id) ON DELETE SET NULL,
+    timestamp TEXT NOT NULL,
+    host TEXT,
+    user TEXT,
+    command TEXT NOT NULL,
+    related_ids TEXT
+);
+
+CREATE INDEX idx_alert_timestamp ON alert (timestamp);
+CREATE INDEX idx_alert_rule ON alert (rule_id, timestamp);
+CREATE INDEX idx_alert_audit_log ON alert (audit_log_id);
@@ -0,0 +1,2 @@
+-- Add up migration script here
+CREATE UNIQUE INDEX idx_alert_rule_audit_log ON alert (rule_id, audit_log_id);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE alert DROP COLUMN acknowledged_by;
+ALTER TABLE alert DROP COLUMN acknowledged_at;
@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE alert ADD COLUMN acknowledged_at TEXT;
+ALTER TABLE alert ADD COLUMN acknowledged_by TEXT;
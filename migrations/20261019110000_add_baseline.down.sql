@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP INDEX idx_baseline_host;
+DROP TABLE baseline;
@@ -0,0 +1,14 @@
+-- Add up migration script here
+CREATE TABLE baseline (
+    kind TEXT NOT NULL,
+    host TEXT NOT NULL,
+    user TEXT NOT NULL,
+    parent TEXT NOT NULL,
+    program TEXT NOT NULL,
+    first_seen TEXT NOT NULL,
+    last_seen TEXT NOT NULL,
+    count INTEGER NOT NULL,
+    PRIMARY KEY (kind, host, user, parent, program)
+);
+
+CREATE INDEX idx_baseline_host ON baseline (host, first_seen);
@@ -0,0 +1,6 @@
+-- Add down migration script here
+DROP TABLE chain_checkpoint;
+DROP TABLE chain_head;
+DROP TABLE audit_log_tombstone;
+ALTER TABLE audit_log DROP COLUMN prev_hash;
+ALTER TABLE audit_log DROP COLUMN hash;
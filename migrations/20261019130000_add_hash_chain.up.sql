@@ -0,0 +1,26 @@
+-- Add up migration script here
+ALTER TABLE audit_log ADD COLUMN hash TEXT;
+ALTER TABLE audit_log ADD COLUMN prev_hash TEXT;
+
+-- Rows removed by a recorded deletion, keeping their place in the chain.
+CREATE TABLE audit_log_tombstone (
+    id INTEGER PRIMARY KEY,
+    hash TEXT,
+    prev_hash TEXT,
+    deletion_id INTEGER NOT NULL REFERENCES audit_log_deletion (id)
+);
+
+CREATE TABLE chain_head (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    audit_log_id INTEGER NOT NULL,
+    hash TEXT NOT NULL
+);
+
+CREATE TABLE chain_checkpoint (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TEXT NOT NULL,
+    audit_log_id INTEGER NOT NULL,
+    hash TEXT NOT NULL,
+    public_key TEXT NOT NULL,
+    signature TEXT NOT NULL
+);
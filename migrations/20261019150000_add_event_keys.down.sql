@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP TABLE file_access_key;
+DROP TABLE audit_log_key;
@@ -0,0 +1,35 @@
+-- Add up migration script here
+CREATE TABLE audit_log_key (
+    audit_log_id INTEGER NOT NULL REFERENCES audit_log (id) ON DELETE CASCADE,
+    key TEXT NOT NULL,
+    PRIMARY KEY (key, audit_log_id)
+) WITHOUT ROWID;
+
+CREATE INDEX idx_audit_log_key_audit_log_id ON audit_log_key (audit_log_id);
+
+CREATE TABLE file_access_key (
+    file_access_id INTEGER NOT NULL REFERENCES file_access (id) ON DELETE CASCADE,
+    key TEXT NOT NULL,
+    PRIMARY KEY (key, file_access_id)
+) WITHOUT ROWID;
+
+CREATE INDEX idx_file_access_key_file_access_id ON file_access_key (file_access_id);
+
+-- Rows store the keys of all matching rules joined with commas.
+INSERT OR IGNORE INTO audit_log_key (audit_log_id, key)
+WITH RECURSIVE split (id, key, rest) AS (
+    SELECT id, '', key || ',' FROM audit_log WHERE key IS NOT NULL
+    UNION ALL
+    SELECT id, substr(rest, 1, instr(rest, ',') - 1), substr(rest, instr(rest, ',') + 1)
+    FROM split WHERE rest != ''
+)
+SELECT id, key FROM split WHERE key != '';
+
+INSERT OR IGNORE INTO file_access_key (file_access_id, key)
+WITH RECURSIVE split (id, key, rest) AS (
+    SELECT id, '', key || ',' FROM file_access WHERE key IS NOT NULL
+    UNION ALL
+    SELECT id, substr(rest, 1, instr(rest, ',') - 1), substr(rest, instr(rest, ',') + 1)
+    FROM split WHERE rest != ''
+)
+SELECT id, key FROM split WHERE key != '';
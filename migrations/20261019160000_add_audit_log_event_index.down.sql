@@ -0,0 +1,2 @@
+-- Add down migration script here
+DROP INDEX idx_audit_log_event;
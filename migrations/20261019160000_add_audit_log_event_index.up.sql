@@ -0,0 +1,2 @@
+-- Add up migration script here
+CREATE UNIQUE INDEX idx_audit_log_event ON audit_log (host, timestamp, serial);
@@ -0,0 +1,284 @@
+use std::{
+    collections::HashMap,
+    fs,
+    future::Future,
+    io::Write,
+    net::IpAddr,
+    path::Path,
+    pin::Pin,
+    str::FromStr,
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use actix_web::{dev::Payload, error, web, FromRequest, HttpRequest, HttpResponse};
+use anyhow::{anyhow, Context, Result};
+use argon2::{
+    password_hash::{rand_core::OsRng, rand_core::RngCore, SaltString},
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::db_util;
+
+pub const SESSION_COOKIE: &str = "auditor_session";
+pub const SESSION_TTL: chrono::Duration = chrono::Duration::hours(12);
+
+const DEFAULT_ADMIN_USERNAME: &str = "admin";
+const TOKEN_BYTES: usize = 32;
+const ADMIN_PASSWORD_FILE: &str = "admin_password";
+
+/// Failed logins a client address gets per window before it is turned away.
+const MAX_FAILED_LOGINS: u32 = 5;
+const FAILED_LOGIN_WINDOW: Duration = Duration::from_secs(15 * 60);
+
+/// Checked when the username does not exist, so unknown users cost as much as wrong passwords.
+const DUMMY_PASSWORD_HASH: &str =
+    "$argon2id$v=19$m=19456,t=2,p=1$Pz/473I2UERws7RGV6nYgQ$AuryxuJ34q7V1YTaSWoQtGEqyXCgQKqrlWmqgquT+gY";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "viewer" => Ok(Role::Viewer),
+            "admin" => Ok(Role::Admin),
+            _ => Err(anyhow!("ERROR: Unknown role '{s}'")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct User {
+    pub id: i64,
+    pub username: String,
+    pub role: Role,
+}
+
+impl TryFrom<db_util::UserRow> for User {
+    type Error = anyhow::Error;
+
+    fn try_from(row: db_util::UserRow) -> Result<Self> {
+        Ok(User {
+            id: row.id,
+            username: row.username,
+            role: row.role.parse()?,
+        })
+    }
+}
+
+pub fn hash_password(password: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    match Argon2::default().hash_password(password.as_bytes(), &salt) {
+        Ok(hash) => Ok(hash.to_string()),
+        Err(err) => Err(anyhow!("ERROR: Could not hash password: {err}")),
+    }
+}
+
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    PasswordHash::new(password_hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    })
+}
+
+/// Verifies a login for a user with `password_hash`, or runs argon2 anyway and fails without one.
+pub fn verify_login(password: &str, password_hash: Option<&str>) -> bool {
+    match password_hash {
+        Some(password_hash) => verify_password(password, password_hash),
+        None => {
+            verify_password(password, DUMMY_PASSWORD_HASH);
+            false
+        }
+    }
+}
+
+/// Counts failed logins per client address within a window.
+#[derive(Debug, Default)]
+pub struct LoginThrottle {
+    failures: Mutex<HashMap<IpAddr, (u32, Instant)>>,
+}
+
+impl LoginThrottle {
+    /// How long `addr` has to wait, once it used up its failed logins.
+    pub fn retry_after(&self, addr: IpAddr) -> Option<Duration> {
+        let failures = self.failures.lock().unwrap_or_else(PoisonError::into_inner);
+        let (count, since) = failures.get(&addr)?;
+        let elapsed = since.elapsed();
+
+        (*count >= MAX_FAILED_LOGINS && elapsed < FAILED_LOGIN_WINDOW)
+            .then(|| FAILED_LOGIN_WINDOW - elapsed)
+    }
+
+    pub fn record_failure(&self, addr: IpAddr) {
+        let mut failures = self.failures.lock().unwrap_or_else(PoisonError::into_inner);
+        failures.retain(|_, (_, since)| since.elapsed() < FAILED_LOGIN_WINDOW);
+        failures.entry(addr).or_insert((0, Instant::now())).0 += 1;
+    }
+
+    pub fn record_success(&self, addr: IpAddr) {
+        let mut failures = self.failures.lock().unwrap_or_else(PoisonError::into_inner);
+        failures.remove(&addr);
+    }
+}
+
+/// Random secret handed out once as a session id or API token.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Tokens are random, so a plain SHA-256 is enough to keep them out of the database.
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Creates the first admin account when no user exists yet.
+///
+/// The password comes from `ADMIN_PASSWORD`, or is generated and written to an owner-only
+/// `admin_password` file in the data directory, so it does not end up in the logs.
+pub async fn ensure_admin(pool: &sqlx::Pool<sqlx::Sqlite>) -> Result<()> {
+    if db_util::count_users(pool).await? > 0 {
+        return Ok(());
+    }
+
+    let username = std::env::var("ADMIN_USERNAME").unwrap_or(DEFAULT_ADMIN_USERNAME.to_string());
+    let password = match std::env::var("ADMIN_PASSWORD") {
+        Ok(password) => password,
+        Err(_) => {
+            let password = generate_token();
+            let path = Path::new(&db_util::data_dir()).join(ADMIN_PASSWORD_FILE);
+            write_admin_password(&path, &password)?;
+            println!("INFO: Wrote generated password for admin user '{username}' to {path:?}");
+            password
+        }
+    };
+
+    db_util::insert_user(
+        pool,
+        &username,
+        &hash_password(&password)?,
+        Role::Admin.as_str(),
+    )
+    .await?;
+
+    println!("INFO: Created admin user '{username}'");
+
+    Ok(())
+}
+
+fn write_admin_password(path: &Path, password: &str) -> Result<()> {
+    // Only readable by the owner from the start, and never replacing an older password file.
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path).context(format!(
+        "ERROR: Could not create admin password file {path:?}"
+    ))?;
+    file.write_all(format!("{password}\n").as_bytes())
+        .and_then(|()| file.sync_all())
+        .context(format!(
+            "ERROR: Could not write admin password file {path:?}"
+        ))
+}
+
+async fn authenticate(req: &HttpRequest) -> Result<User, error::Error> {
+    let unauthorized = |message: &str| {
+        error::InternalError::from_response(
+            message.to_string(),
+            HttpResponse::Unauthorized().json(message),
+        )
+    };
+
+    let pool = req
+        .app_data::<web::Data<sqlx::SqlitePool>>()
+        .ok_or_else(|| error::ErrorInternalServerError("ERROR: Missing db pool"))?;
+
+    let bearer = req
+        .headers()
+        .get(actix_web::http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    let user = match (bearer, req.cookie(SESSION_COOKIE)) {
+        (Some(token), _) => db_util::fetch_api_token_user(pool, &hash_token(token.trim())).await,
+        (None, Some(cookie)) => {
+            db_util::fetch_session_user(pool, &hash_token(cookie.value())).await
+        }
+        (None, None) => return Err(unauthorized("ERROR: Authentication required").into()),
+    }
+    .map_err(error::ErrorInternalServerError)?;
+
+    match user {
+        Some(user) => User::try_from(user).map_err(error::ErrorInternalServerError),
+        None => Err(unauthorized("ERROR: Invalid or expired credentials").into()),
+    }
+}
+
+/// Any authenticated user, either by session cookie or `Authorization: Bearer` token.
+#[derive(Debug, Clone)]
+pub struct AuthUser(pub User);
+
+impl FromRequest for AuthUser {
+    type Error = error::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move { authenticate(&req).await.map(AuthUser) })
+    }
+}
+
+/// An authenticated user with the admin role.
+#[derive(Debug, Clone)]
+pub struct AdminUser(pub User);
+
+impl FromRequest for AdminUser {
+    type Error = error::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            let user = authenticate(&req).await?;
+            if user.role != Role::Admin {
+                let message = "ERROR: Admin role required";
+                return Err(error::InternalError::from_response(
+                    message,
+                    HttpResponse::Forbidden().json(message),
+                )
+                .into());
+            }
+
+            Ok(AdminUser(user))
+        })
+    }
+}
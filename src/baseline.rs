@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db_util,
+    rules::{NewAlert, RuleEvent, Severity},
+};
+
+pub const DEFAULT_LEARNING_DAYS: i64 = 7;
+
+/// What a baseline entry records about the execs of a host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BaselineKind {
+    /// A program path.
+    Program,
+    /// A program run by a user.
+    UserProgram,
+    /// A program started by the latest exec of its parent.
+    ParentChild,
+}
+
+impl BaselineKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BaselineKind::Program => "program",
+            BaselineKind::UserProgram => "user_program",
+            BaselineKind::ParentChild => "parent_child",
+        }
+    }
+
+    /// Id of the alerts raised when an entry of this kind is first seen.
+    pub fn rule_id(&self) -> &'static str {
+        match self {
+            BaselineKind::Program => "first-seen-program",
+            BaselineKind::UserProgram => "first-seen-user-program",
+            BaselineKind::ParentChild => "first-seen-parent-child",
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            BaselineKind::Program => "Program first seen on host",
+            BaselineKind::UserProgram => "Program first run by user",
+            BaselineKind::ParentChild => "Program first started by this parent",
+        }
+    }
+
+    fn severity(&self) -> Severity {
+        match self {
+            BaselineKind::Program => Severity::Medium,
+            BaselineKind::UserProgram | BaselineKind::ParentChild => Severity::Low,
+        }
+    }
+}
+
+/// One entry of the baseline of a host, with `""` for the parts its kind does not use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Observation {
+    pub kind: BaselineKind,
+    pub host: String,
+    pub user: String,
+    pub parent: String,
+    pub program: String,
+}
+
+impl Observation {
+    /// The entries `event` adds to the baseline of its host, the program first.
+    ///
+    /// Programs are keyed on their executable, as argv[0] is whatever the caller passed.
+    /// Execs stored without one fall back to argv[0].
+    pub fn of(event: &RuleEvent) -> Vec<Observation> {
+        let program = event.exe.as_ref().unwrap_or(&event.program);
+        let parent_program = event.parent_exe.as_ref().or(event.parent_program.as_ref());
+        let observation = |kind, user: Option<&String>, parent: Option<&String>| Observation {
+            kind,
+            host: event.host.clone().unwrap_or_default(),
+            user: user.cloned().unwrap_or_default(),
+            parent: parent.cloned().unwrap_or_default(),
+            program: program.clone(),
+        };
+
+        let mut observations = vec![observation(BaselineKind::Program, None, None)];
+        if event.user.is_some() {
+            observations.push(observation(
+                BaselineKind::UserProgram,
+                event.user.as_ref(),
+                None,
+            ));
+        }
+        if parent_program.is_some() {
+            observations.push(observation(BaselineKind::ParentChild, None, parent_program));
+        }
+        observations
+    }
+}
+
+/// Stored baseline entry.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct BaselineEntry {
+    pub kind: String,
+    pub host: String,
+    pub user: String,
+    pub parent: String,
+    pub program: String,
+    pub first_seen: String,
+    pub last_seen: String,
+    pub count: i64,
+}
+
+/// Field filters for the baseline endpoint.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct BaselineFilter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<BaselineKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    /// User name or numeric uid.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub program: Option<String>,
+}
+
+/// Learns the programs run on each host, raising alerts for new ones once the host has
+/// been watched for the learning period.
+#[derive(Debug)]
+pub struct Baseline {
+    learning_period: Duration,
+    /// When each host was first seen, by host name.
+    learning_since: HashMap<String, DateTime<Utc>>,
+}
+
+impl Baseline {
+    pub fn new(learning_period: Duration) -> Self {
+        Baseline {
+            learning_period,
+            learning_since: HashMap::new(),
+        }
+    }
+
+    /// Adds `event` to the baseline, returning alerts for the entries it saw first.
+    ///
+    /// A program new to the host is necessarily new to its user and parent too, so only
+    /// the program is alerted on then.
+    pub async fn observe(
+        &mut self,
+        pool: &sqlx::Pool<sqlx::Sqlite>,
+        event: &RuleEvent,
+    ) -> Result<Vec<NewAlert>> {
+        let host = event.host.clone().unwrap_or_default();
+        let since = match self.learning_since.get(&host) {
+            Some(since) => *since,
+            None => {
+                let since = db_util::fetch_baseline_start(pool, &host)
+                    .await?
+                    .unwrap_or(event.timestamp);
+                self.learning_since.insert(host, since);
+                since
+            }
+        };
+        let learning = event.timestamp < since + self.learning_period;
+
+        let mut alerts = Vec::new();
+        let mut new_program = false;
+        for observation in Observation::of(event) {
+            let new = db_util::record_baseline(pool, &observation, event.timestamp).await?;
+            if !new || learning || new_program {
+                continue;
+            }
+
+            new_program = observation.kind == BaselineKind::Program;
+            alerts.push(NewAlert::new(
+                observation.kind.rule_id(),
+                observation.kind.title(),
+                observation.kind.severity(),
+                event,
+                Vec::new(),
+            ));
+        }
+
+        Ok(alerts)
+    }
+}
@@ -0,0 +1,440 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{ErrorKind, Write},
+    path::Path,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context, Result};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::{SecondsFormat, Utc};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{db_util, parser};
+
+/// Previous hash of the first row of the chain.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+pub const DEFAULT_CHECKPOINT_SECS: u64 = 3600;
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn decode_hex<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    bytes.try_into().ok()
+}
+
+/// Stored fields of an audit log covered by its hash, every column but the id and hashes.
+#[derive(Debug, Clone, Default, Serialize, sqlx::FromRow)]
+pub struct ChainFields {
+    pub log_type: String,
+    pub timestamp: String,
+    pub program: String,
+    pub args: String,
+    pub argc: i64,
+    pub command: String,
+    pub serial: Option<i64>,
+    pub host: Option<String>,
+    /// Skipped when unset, so rows chained before it was stored keep their hash.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exe: Option<String>,
+    pub pid: Option<i64>,
+    pub ppid: Option<i64>,
+    pub uid: Option<i64>,
+    pub user: Option<String>,
+    pub ses: Option<i64>,
+    pub tty: Option<String>,
+    pub auid: Option<i64>,
+    pub login_user: Option<String>,
+    pub key: Option<String>,
+    pub success: Option<bool>,
+    pub exit: Option<i64>,
+    pub raw: Option<String>,
+    pub original: Option<Vec<u8>>,
+}
+
+impl ChainFields {
+    /// The fields `audit_log` is stored with.
+    pub fn of(audit_log: &parser::AuditLog) -> Self {
+        ChainFields {
+            log_type: audit_log.log_type.clone(),
+            timestamp: audit_log
+                .timestamp
+                .to_rfc3339_opts(SecondsFormat::AutoSi, false),
+            program: audit_log.program.clone(),
+            args: audit_log.get_args_string(),
+            argc: audit_log.argc as i64,
+            command: audit_log.command.clone(),
+            serial: Some(audit_log.serial as i64),
+            host: audit_log.host.clone(),
+            exe: audit_log.exe.clone(),
+            pid: audit_log.pid,
+            ppid: audit_log.ppid,
+            uid: audit_log.uid,
+            user: audit_log.user.clone(),
+            ses: audit_log.ses,
+            tty: audit_log.tty.clone(),
+            auid: audit_log.auid,
+            login_user: audit_log.login_user.clone(),
+            key: audit_log.key.clone(),
+            success: audit_log.success,
+            exit: audit_log.exit,
+            raw: Some(audit_log.raw.join("\n")),
+            original: audit_log.original.clone(),
+        }
+    }
+
+    /// Hash of a row with these fields following the row hashed to `prev_hash`.
+    pub fn hash(&self, prev_hash: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(prev_hash.as_bytes());
+        hasher.update(b"\n");
+        hasher.update(serde_json::to_vec(self).unwrap_or_default());
+        encode_hex(&hasher.finalize())
+    }
+}
+
+/// A row of the chain in id order, live or removed by a recorded deletion.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ChainEntry {
+    pub id: i64,
+    pub hash: Option<String>,
+    pub prev_hash: Option<String>,
+    /// Only the hashes are left of rows removed by a recorded deletion.
+    pub deleted: bool,
+    #[sqlx(flatten)]
+    pub fields: ChainFields,
+}
+
+/// Latest row of the chain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, sqlx::FromRow)]
+pub struct ChainHead {
+    pub audit_log_id: i64,
+    pub hash: String,
+}
+
+/// Signed statement that the chain ended at `audit_log_id` with `hash` at `created_at`.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Checkpoint {
+    pub id: i64,
+    pub created_at: String,
+    pub audit_log_id: i64,
+    pub hash: String,
+    pub public_key: String,
+    pub signature: String,
+}
+
+fn checkpoint_message(created_at: &str, head: &ChainHead) -> String {
+    format!(
+        "auditor-checkpoint:{created_at}:{}:{}",
+        head.audit_log_id, head.hash
+    )
+}
+
+impl Checkpoint {
+    /// Checks the signature against the trusted `key`.
+    pub fn verify(&self, key: &VerifyingKey) -> Result<(), String> {
+        if decode_hex::<32>(&self.public_key) != Some(key.to_bytes()) {
+            return Err("signed by another key".to_string());
+        }
+
+        let signature = decode_hex::<64>(&self.signature)
+            .map(|bytes| Signature::from_bytes(&bytes))
+            .ok_or("signature is invalid")?;
+        let head = ChainHead {
+            audit_log_id: self.audit_log_id,
+            hash: self.hash.clone(),
+        };
+        key.verify(
+            checkpoint_message(&self.created_at, &head).as_bytes(),
+            &signature,
+        )
+        .map_err(|_| "signature does not match".to_string())
+    }
+}
+
+/// Signing key from the file at `path`, holding 64 hex digits, `None` if it is missing.
+fn read_signing_key(path: &Path) -> Result<Option<SigningKey>> {
+    let hex = match fs::read_to_string(path) {
+        Ok(hex) => hex,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(anyhow!("ERROR: Could not read chain key {path:?}: {err}")),
+    };
+    let seed = decode_hex::<32>(hex.trim())
+        .ok_or(anyhow!("ERROR: Chain key {path:?} must hold 64 hex digits"))?;
+    Ok(Some(SigningKey::from_bytes(&seed)))
+}
+
+/// Signing key from the file at `CHAIN_KEY`, created if missing.
+pub fn load_signing_key() -> Result<Option<SigningKey>> {
+    let Ok(path) = env::var("CHAIN_KEY") else {
+        return Ok(None);
+    };
+    let path = Path::new(&path);
+    if let Some(key) = read_signing_key(path)? {
+        return Ok(Some(key));
+    }
+
+    let mut seed = [0u8; 32];
+    OsRng.fill_bytes(&mut seed);
+    // Only readable by the owner from the start, and never replacing a key created since.
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .context(format!("ERROR: Could not create chain key {path:?}"))?;
+    file.write_all(encode_hex(&seed).as_bytes())
+        .and_then(|()| file.sync_all())
+        .context(format!("ERROR: Could not write chain key {path:?}"))?;
+
+    let key = SigningKey::from_bytes(&seed);
+    println!(
+        "INFO: Created chain key {path:?} with public key {}",
+        encode_hex(key.verifying_key().as_bytes())
+    );
+    Ok(Some(key))
+}
+
+/// Trusted key checkpoints are verified with, from `CHAIN_PUBLIC_KEY` or else an existing
+/// `CHAIN_KEY`, which is never created here.
+pub fn load_verifying_key() -> Result<Option<VerifyingKey>> {
+    if let Ok(hex) = env::var("CHAIN_PUBLIC_KEY") {
+        return decode_hex::<32>(hex.trim())
+            .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+            .map(Some)
+            .ok_or(anyhow!(
+                "ERROR: CHAIN_PUBLIC_KEY must be a 64 hex digit ed25519 public key"
+            ));
+    }
+
+    match env::var("CHAIN_KEY") {
+        Ok(path) => Ok(read_signing_key(Path::new(&path))?.map(|key| key.verifying_key())),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Signs the current head of the chain, unless it was already signed.
+pub async fn checkpoint(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    key: &SigningKey,
+) -> Result<Option<Checkpoint>> {
+    let Some(head) = db_util::fetch_chain_head(pool).await? else {
+        return Ok(None);
+    };
+    let last = db_util::fetch_checkpoints(pool).await?.pop();
+    if last.is_some_and(|last| last.audit_log_id == head.audit_log_id && last.hash == head.hash) {
+        return Ok(None);
+    }
+
+    let created_at = Utc::now().to_rfc3339_opts(SecondsFormat::AutoSi, false);
+    let signature = key.sign(checkpoint_message(&created_at, &head).as_bytes());
+    let checkpoint = db_util::insert_checkpoint(
+        pool,
+        &created_at,
+        &head,
+        &encode_hex(key.verifying_key().as_bytes()),
+        &encode_hex(&signature.to_bytes()),
+    )
+    .await?;
+    Ok(Some(checkpoint))
+}
+
+/// Signs the head of the chain every `interval`.
+pub async fn run_checkpoints(pool: sqlx::Pool<sqlx::Sqlite>, key: SigningKey, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        if let Err(err) = checkpoint(&pool, &key).await {
+            eprintln!("{err}");
+        }
+    }
+}
+
+/// Evidence that the stored audit logs were changed outside of a recorded deletion.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Problem {
+    /// The row no longer matches its hash.
+    Modified { id: i64 },
+    /// Rows between these two are gone, `before_id` is `None` when the end of the chain is.
+    Missing {
+        after_id: Option<i64>,
+        before_id: Option<i64>,
+    },
+    /// A row without a hash after the chain started.
+    Unchained { id: i64 },
+    /// The chain head does not match the last row.
+    Head { reason: String },
+    /// Checkpoints between these two are gone, `before_id` is `None` when the latest are.
+    MissingCheckpoints {
+        after_id: Option<i64>,
+        before_id: Option<i64>,
+    },
+    Checkpoint {
+        id: i64,
+        audit_log_id: i64,
+        reason: String,
+    },
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct VerifyReport {
+    pub ok: bool,
+    /// Live rows whose hash was checked.
+    pub checked: u64,
+    /// Rows removed by recorded deletions.
+    pub deleted: u64,
+    /// Rows stored before the chain started.
+    pub unchained: u64,
+    /// Checkpoints whose signature and hash matched.
+    pub checkpoints: u64,
+    /// Checkpoints matching the chain whose signature was not checked, for lack of a
+    /// trusted key.
+    pub unverified_checkpoints: u64,
+    pub head: Option<ChainHead>,
+    pub problems: Vec<Problem>,
+}
+
+const VERIFY_BATCH_SIZE: i64 = 1000;
+
+/// Walks the chain in id order, reporting every row changed or removed outside a
+/// recorded deletion and every checkpoint that does not match.
+pub async fn verify(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    key: Option<&VerifyingKey>,
+) -> Result<VerifyReport> {
+    let mut report = VerifyReport {
+        head: db_util::fetch_chain_head(pool).await?,
+        ..Default::default()
+    };
+
+    // Checkpoint ids are never reused, so a gap means checkpoints were removed.
+    let mut checkpoints: HashMap<i64, Vec<Checkpoint>> = HashMap::new();
+    let mut last_checkpoint_id = 0;
+    for checkpoint in db_util::fetch_checkpoints(pool).await? {
+        if checkpoint.id != last_checkpoint_id + 1 {
+            report.problems.push(Problem::MissingCheckpoints {
+                after_id: (last_checkpoint_id > 0).then_some(last_checkpoint_id),
+                before_id: Some(checkpoint.id),
+            });
+        }
+        last_checkpoint_id = checkpoint.id;
+
+        match key.map(|key| checkpoint.verify(key)) {
+            Some(Err(reason)) => report.problems.push(Problem::Checkpoint {
+                id: checkpoint.id,
+                audit_log_id: checkpoint.audit_log_id,
+                reason,
+            }),
+            _ => checkpoints
+                .entry(checkpoint.audit_log_id)
+                .or_default()
+                .push(checkpoint),
+        }
+    }
+    if db_util::fetch_last_checkpoint_id(pool).await? > last_checkpoint_id {
+        report.problems.push(Problem::MissingCheckpoints {
+            after_id: (last_checkpoint_id > 0).then_some(last_checkpoint_id),
+            before_id: None,
+        });
+    }
+
+    let mut last: Option<ChainHead> = None;
+    let mut after_id = 0;
+    loop {
+        let entries = db_util::fetch_chain_entries(pool, after_id, VERIFY_BATCH_SIZE).await?;
+        let Some(entry) = entries.last() else {
+            break;
+        };
+        after_id = entry.id;
+
+        for entry in entries {
+            let Some(hash) = entry.hash else {
+                match last {
+                    None => report.unchained += 1,
+                    Some(_) => report.problems.push(Problem::Unchained { id: entry.id }),
+                }
+                continue;
+            };
+
+            let expected_prev = last.as_ref().map_or(GENESIS_HASH, |last| &last.hash);
+            if entry.prev_hash.as_deref() != Some(expected_prev) {
+                report.problems.push(Problem::Missing {
+                    after_id: last.as_ref().map(|last| last.audit_log_id),
+                    before_id: Some(entry.id),
+                });
+            }
+
+            if entry.deleted {
+                report.deleted += 1;
+            } else {
+                report.checked += 1;
+                let prev_hash = entry.prev_hash.as_deref().unwrap_or(GENESIS_HASH);
+                if entry.fields.hash(prev_hash) != hash {
+                    report.problems.push(Problem::Modified { id: entry.id });
+                }
+            }
+
+            for checkpoint in checkpoints.remove(&entry.id).unwrap_or_default() {
+                if checkpoint.hash != hash {
+                    report.problems.push(Problem::Checkpoint {
+                        id: checkpoint.id,
+                        audit_log_id: checkpoint.audit_log_id,
+                        reason: "hash does not match the chain".to_string(),
+                    });
+                } else if key.is_some() {
+                    report.checkpoints += 1;
+                } else {
+                    report.unverified_checkpoints += 1;
+                }
+            }
+
+            last = Some(ChainHead {
+                audit_log_id: entry.id,
+                hash,
+            });
+        }
+    }
+
+    for checkpoint in checkpoints.into_values().flatten() {
+        report.problems.push(Problem::Checkpoint {
+            id: checkpoint.id,
+            audit_log_id: checkpoint.audit_log_id,
+            reason: "row is missing from the chain".to_string(),
+        });
+    }
+
+    let last_id = last.as_ref().map(|last| last.audit_log_id);
+    match (&report.head, &last) {
+        (Some(head), Some(last)) if head == last => {}
+        (Some(head), _) if last_id.is_none_or(|last_id| last_id < head.audit_log_id) => {
+            report.problems.push(Problem::Missing {
+                after_id: last_id,
+                before_id: None,
+            })
+        }
+        (Some(_), _) => report.problems.push(Problem::Head {
+            reason: "chain head does not match the last row".to_string(),
+        }),
+        (None, Some(_)) => report.problems.push(Problem::Head {
+            reason: "chain head is missing".to_string(),
+        }),
+        (None, None) => {}
+    }
+
+    report.ok = report.problems.is_empty();
+    Ok(report)
+}
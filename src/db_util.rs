@@ -1,23 +1,45 @@
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
 use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
 use dotenvy::dotenv;
+use futures::TryStreamExt;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
 use sqlx::{
     migrate::{MigrateDatabase, Migrator},
     pool::PoolConnection,
-    query, query_as,
-    sqlite::SqliteQueryResult,
-    Pool, Sqlite, SqlitePool,
+    query, query_as, query_scalar,
+    sqlite::{SqliteConnectOptions, SqliteQueryResult},
+    Pool, QueryBuilder, Sqlite, SqlitePool,
 };
 
-use crate::parser;
+use crate::{
+    baseline, chain, errno, file_access, login, parser, redact, rules, session, stats, sudo, tree,
+};
 
 const DATA_DIR: &str = "./";
 
 static MIGRATOR: Migrator = sqlx::migrate!();
 
+/// Directory holding the database and files created next to it.
+pub fn data_dir() -> String {
+    std::env::var("DATA_DIR").unwrap_or(DATA_DIR.to_string())
+}
+
 pub async fn init_database() -> Result<Pool<Sqlite>> {
     dotenv().ok();
 
-    let data_dir = std::env::var("DATA_DIR").unwrap_or(DATA_DIR.to_string());
+    let data_dir = data_dir();
     if !std::path::Path::new(&data_dir).exists() {
         std::fs::create_dir_all(&data_dir)?;
     }
@@ -28,7 +50,8 @@ pub async fn init_database() -> Result<Pool<Sqlite>> {
         Sqlite::create_database(&db_url).await?;
     }
 
-    let pool = SqlitePool::connect(&db_url).await.unwrap();
+    let options = SqliteConnectOptions::from_str(&db_url)?.with_regexp();
+    let pool = SqlitePool::connect_with(options).await.unwrap();
 
     MIGRATOR.run(&pool).await?;
 
@@ -46,7 +69,7 @@ pub async fn fetch_audit_logs(pool: &Pool<Sqlite>) -> Result<Vec<parser::AuditLo
 
     let audit_logs = match query_as!(
         parser::AuditLogResponse,
-        "SELECT timestamp, command FROM audit_log ORDER BY timestamp DESC"
+        "SELECT id as \"id!\", timestamp, command, key, success FROM audit_log ORDER BY timestamp DESC"
     )
     .fetch_all(&mut *conn)
     .await
@@ -60,35 +83,527 @@ pub async fn fetch_audit_logs(pool: &Pool<Sqlite>) -> Result<Vec<parser::AuditLo
     Ok(audit_logs)
 }
 
-pub async fn fetch_audit_logs_paginated(
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+/// Escapes `value` to match literally in a `LIKE` pattern with `ESCAPE '\'`.
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Field filters shared by the endpoints listing audit logs.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct AuditLogFilter {
+    /// Inclusive lower bound on the timestamp.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<DateTime<Utc>>,
+    /// Exclusive upper bound on the timestamp.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<DateTime<Utc>>,
+    /// Full program path or its file name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub program: Option<String>,
+    /// User name or numeric uid.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<i64>,
+    /// Key of the audit rule, matching any of the keys of events logged with several.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// `false` for failed executions only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub success: Option<bool>,
+}
+
+impl AuditLogFilter {
+    pub fn is_empty(&self) -> bool {
+        self.from.is_none()
+            && self.to.is_none()
+            && self.program.is_none()
+            && self.user.is_none()
+            && self.host.is_none()
+            && self.pid.is_none()
+            && self.key.is_none()
+            && self.success.is_none()
+    }
+
+    /// Checks an audit log that is not stored yet against the same conditions as the query.
+    pub fn matches(&self, audit_log: &parser::AuditLog) -> bool {
+        self.from.is_none_or(|from| audit_log.timestamp >= from)
+            && self.to.is_none_or(|to| audit_log.timestamp < to)
+            && self.program.as_ref().is_none_or(|program| {
+                audit_log.program == *program || audit_log.program.ends_with(&format!("/{program}"))
+            })
+            && self.user.as_ref().is_none_or(|user| {
+                audit_log.user.as_ref() == Some(user)
+                    || audit_log.uid.is_some_and(|uid| uid.to_string() == *user)
+            })
+            && self
+                .host
+                .as_ref()
+                .is_none_or(|host| audit_log.host.as_ref() == Some(host))
+            && self.pid.is_none_or(|pid| audit_log.pid == Some(pid))
+            && self.key.as_ref().is_none_or(|key| {
+                audit_log
+                    .key
+                    .as_ref()
+                    .is_some_and(|keys| keys.split(',').any(|k| k == key))
+            })
+            && self
+                .success
+                .is_none_or(|success| audit_log.success.unwrap_or(true) == success)
+    }
+
+    fn push_conditions(&self, builder: &mut QueryBuilder<'_, Sqlite>) {
+        if let Some(from) = self.from {
+            builder.push(" AND timestamp >= ").push_bind(from);
+        }
+        if let Some(to) = self.to {
+            builder.push(" AND timestamp < ").push_bind(to);
+        }
+        if let Some(program) = &self.program {
+            builder
+                .push(" AND (program = ")
+                .push_bind(program.clone())
+                .push(" OR program LIKE '%/' || ")
+                .push_bind(escape_like(program))
+                .push(" ESCAPE '\\')");
+        }
+        if let Some(user) = &self.user {
+            builder
+                .push(" AND (user = ")
+                .push_bind(user.clone())
+                .push(" OR CAST(uid AS TEXT) = ")
+                .push_bind(user.clone())
+                .push(")");
+        }
+        if let Some(host) = &self.host {
+            builder.push(" AND host = ").push_bind(host.clone());
+        }
+        if let Some(pid) = self.pid {
+            builder.push(" AND pid = ").push_bind(pid);
+        }
+        if let Some(key) = &self.key {
+            builder
+                .push(" AND id IN (SELECT audit_log_id FROM audit_log_key WHERE key = ")
+                .push_bind(key.clone())
+                .push(")");
+        }
+        // Logs stored before results were recorded count as successful.
+        if let Some(success) = self.success {
+            builder
+                .push(" AND COALESCE(success, TRUE) = ")
+                .push_bind(success);
+        }
+    }
+}
+
+/// Rule keys of a row, which stores the keys of every matching rule joined with commas.
+fn split_keys(key: Option<&str>) -> impl Iterator<Item = &str> {
+    key.into_iter()
+        .flat_map(|key| key.split(','))
+        .filter(|key| !key.is_empty())
+}
+
+/// Position of the last row of a page, encoded as `<timestamp nanos>_<id>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    pub timestamp: String,
+    pub id: i64,
+}
+
+impl Cursor {
+    pub fn from_response(audit_log: &parser::AuditLogResponse) -> Option<Cursor> {
+        DateTime::parse_from_rfc3339(&audit_log.timestamp)
+            .ok()
+            .map(|_| Cursor {
+                timestamp: audit_log.timestamp.clone(),
+                id: audit_log.id,
+            })
+    }
+
+    pub fn encode(&self) -> String {
+        let nanos = DateTime::parse_from_rfc3339(&self.timestamp)
+            .ok()
+            .and_then(|timestamp| timestamp.timestamp_nanos_opt())
+            .unwrap_or_default();
+
+        format!("{nanos}_{id}", id = self.id)
+    }
+
+    pub fn decode(cursor: &str) -> Result<Cursor> {
+        let (nanos, id) = cursor
+            .split_once('_')
+            .ok_or(anyhow!("ERROR: Invalid cursor '{cursor}'"))?;
+        let nanos: i64 = nanos
+            .parse()
+            .map_err(|_| anyhow!("ERROR: Invalid cursor '{cursor}'"))?;
+        let id: i64 = id
+            .parse()
+            .map_err(|_| anyhow!("ERROR: Invalid cursor '{cursor}'"))?;
+
+        Ok(Cursor {
+            // Matches the encoding sqlx uses for `DateTime<Utc>` in SQLite.
+            timestamp: DateTime::from_timestamp_nanos(nanos)
+                .to_rfc3339_opts(chrono::SecondsFormat::AutoSi, false),
+            id,
+        })
+    }
+}
+
+fn filtered_query<'a>(
+    columns: &str,
+    filter: &AuditLogFilter,
+    order: SortOrder,
+    cursor: Option<&Cursor>,
+    limit: i64,
+    offset: i64,
+) -> QueryBuilder<'a, Sqlite> {
+    let mut builder = QueryBuilder::new(format!("SELECT {columns} FROM audit_log WHERE 1 = 1"));
+    filter.push_conditions(&mut builder);
+
+    let (comparison, direction) = match order {
+        SortOrder::Asc => (">", "ASC"),
+        SortOrder::Desc => ("<", "DESC"),
+    };
+    if let Some(cursor) = cursor {
+        builder
+            .push(format!(" AND (timestamp, id) {comparison} ("))
+            .push_bind(cursor.timestamp.clone())
+            .push(", ")
+            .push_bind(cursor.id)
+            .push(")");
+    }
+    builder
+        .push(format!(
+            " ORDER BY timestamp {direction}, id {direction} LIMIT "
+        ))
+        .push_bind(limit)
+        .push(" OFFSET ")
+        .push_bind(offset);
+
+    builder
+}
+
+pub async fn fetch_audit_logs_filtered(
     pool: &Pool<Sqlite>,
-    page: i64,
-    page_size: i64,
+    filter: &AuditLogFilter,
+    order: SortOrder,
+    cursor: Option<&Cursor>,
+    limit: i64,
+    offset: i64,
 ) -> Result<Vec<parser::AuditLogResponse>> {
     let mut conn = get_connection(pool)
         .await
         .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
 
-    let offset = (page - 1) * page_size;
+    let mut builder = filtered_query(
+        "id, timestamp, command, key, success",
+        filter,
+        order,
+        cursor,
+        limit,
+        offset,
+    );
 
-    let audit_logs = match query_as!(
-        parser::AuditLogResponse,
-        "SELECT timestamp, command FROM audit_log ORDER BY timestamp DESC LIMIT ? OFFSET ?",
-        page_size,
-        offset
+    let audit_logs = match builder
+        .build_query_as::<parser::AuditLogResponse>()
+        .fetch_all(&mut *conn)
+        .await
+    {
+        Ok(query_result) => query_result,
+        Err(err) => {
+            return Err(anyhow!("ERROR: Could not fetch filtered audit_logs: {err}"));
+        }
+    };
+
+    Ok(audit_logs)
+}
+
+pub async fn count_audit_logs(pool: &Pool<Sqlite>, filter: &AuditLogFilter) -> Result<i64> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let mut builder = QueryBuilder::new("SELECT COUNT(*) FROM audit_log WHERE 1 = 1");
+    filter.push_conditions(&mut builder);
+
+    let count = match builder
+        .build_query_scalar::<i64>()
+        .fetch_one(&mut *conn)
+        .await
+    {
+        Ok(query_result) => query_result,
+        Err(err) => {
+            return Err(anyhow!("ERROR: Could not count audit_logs: {err}"));
+        }
+    };
+
+    Ok(count)
+}
+
+const AUDIT_LOG_DETAIL_COLUMNS: &str =
+    "id, log_type, timestamp, program, args, argc, command, serial, host, exe, pid, ppid, uid, user, ses, tty, auid, login_user, key, success, exit, raw";
+
+#[derive(Debug, sqlx::FromRow)]
+struct AuditLogRow {
+    id: i64,
+    log_type: String,
+    timestamp: String,
+    program: String,
+    args: String,
+    argc: i64,
+    command: String,
+    serial: Option<i64>,
+    host: Option<String>,
+    exe: Option<String>,
+    pid: Option<i64>,
+    ppid: Option<i64>,
+    uid: Option<i64>,
+    user: Option<String>,
+    ses: Option<i64>,
+    tty: Option<String>,
+    auid: Option<i64>,
+    login_user: Option<String>,
+    key: Option<String>,
+    success: Option<bool>,
+    exit: Option<i64>,
+    raw: Option<String>,
+}
+
+impl From<AuditLogRow> for parser::AuditLogDetail {
+    fn from(row: AuditLogRow) -> Self {
+        parser::AuditLogDetail {
+            id: row.id,
+            log_type: row.log_type,
+            timestamp: row.timestamp,
+            program: row.program,
+            args: row.args,
+            argc: row.argc,
+            command: row.command,
+            serial: row.serial,
+            host: row.host,
+            exe: row.exe,
+            pid: row.pid,
+            ppid: row.ppid,
+            uid: row.uid,
+            user: row.user,
+            ses: row.ses,
+            tty: row.tty,
+            auid: row.auid,
+            login_user: row.login_user,
+            key: row.key,
+            success: row.success,
+            exit: row.exit,
+            errno: row
+                .exit
+                .filter(|_| row.success == Some(false))
+                .and_then(errno::errno_name),
+            raw: row
+                .raw
+                .map(|raw| raw.lines().map(String::from).collect())
+                .unwrap_or_default(),
+            sudo: None,
+        }
+    }
+}
+
+pub async fn fetch_audit_log(
+    pool: &Pool<Sqlite>,
+    id: i64,
+) -> Result<Option<parser::AuditLogDetail>> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let row = match query_as!(
+        AuditLogRow,
+        "SELECT id, log_type, timestamp, program, args, argc, command, serial, host, exe, pid, ppid, uid, user, ses, tty, auid, login_user, key, success, exit, raw FROM audit_log WHERE id = ?",
+        id
     )
-    .fetch_all(&mut *conn)
+    .fetch_optional(&mut *conn)
     .await
     {
         Ok(query_result) => query_result,
+        Err(err) => {
+            return Err(anyhow!("ERROR: Could not fetch audit_log {id}: {err}"));
+        }
+    };
+
+    let Some(mut audit_log) = row.map(parser::AuditLogDetail::from) else {
+        return Ok(None);
+    };
+
+    let mut builder = QueryBuilder::new(SUDO_COMMANDS_QUERY);
+    builder.push(" AND audit_log_id = ").push_bind(id);
+    audit_log.sudo = match builder
+        .build_query_as::<sudo::SudoCommand>()
+        .fetch_optional(&mut *conn)
+        .await
+    {
+        Ok(sudo_command) => sudo_command,
         Err(err) => {
             return Err(anyhow!(
-                "ERROR: Could not fetch paginated audit_logs: {err}"
+                "ERROR: Could not fetch sudo command of audit_log {id}: {err}"
             ));
         }
     };
 
-    Ok(audit_logs)
+    Ok(Some(audit_log))
+}
+
+/// Encrypted original of audit log `id`, `None` when it was not redacted or is gone.
+/// Encrypted original of audit_log `id`, along with the row it was encrypted for.
+pub async fn fetch_audit_log_original(
+    pool: &Pool<Sqlite>,
+    id: i64,
+) -> Result<Option<(Vec<u8>, redact::OriginalRow)>> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    match query!(
+        "SELECT original, host, timestamp, serial FROM audit_log WHERE id = ?",
+        id
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    {
+        Ok(row) => Ok(row.and_then(|row| {
+            row.original.map(|original| {
+                let original_row = redact::OriginalRow {
+                    host: row.host,
+                    timestamp: row.timestamp,
+                    serial: row.serial,
+                };
+                (original, original_row)
+            })
+        })),
+        Err(err) => Err(anyhow!(
+            "ERROR: Could not fetch original of audit_log {id}: {err}"
+        )),
+    }
+}
+
+/// Like [`fetch_audit_logs_filtered`], but returns every stored field.
+pub async fn fetch_audit_log_details_filtered(
+    pool: &Pool<Sqlite>,
+    filter: &AuditLogFilter,
+    order: SortOrder,
+    cursor: Option<&Cursor>,
+    limit: i64,
+) -> Result<Vec<parser::AuditLogDetail>> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let mut builder = filtered_query(AUDIT_LOG_DETAIL_COLUMNS, filter, order, cursor, limit, 0);
+
+    let audit_logs = match builder
+        .build_query_as::<AuditLogRow>()
+        .fetch_all(&mut *conn)
+        .await
+    {
+        Ok(query_result) => query_result,
+        Err(err) => {
+            return Err(anyhow!("ERROR: Could not fetch filtered audit_logs: {err}"));
+        }
+    };
+
+    Ok(audit_logs
+        .into_iter()
+        .map(parser::AuditLogDetail::from)
+        .collect())
+}
+
+/// Error of a regex search that ran past its deadline, as opposed to a failed query.
+#[derive(Debug)]
+pub struct RegexSearchTimeout;
+
+impl fmt::Display for RegexSearchTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ERROR: Regex search timed out")
+    }
+}
+
+impl std::error::Error for RegexSearchTimeout {}
+
+/// Lets queries on the connection run again once dropped, so a search that is cancelled or
+/// fails before removing its progress handler does not interrupt later users of the
+/// pooled connection.
+struct ProgressHandlerGuard(Arc<AtomicBool>);
+
+impl Drop for ProgressHandlerGuard {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Streams the rows whose command matches `pattern` using SQLite's REGEXP operator,
+/// optionally only those logged by the rule `key`.
+///
+/// The query is interrupted once `deadline` has passed, and at most `limit` rows are read.
+pub async fn regex_search_audit_logs(
+    pool: &Pool<Sqlite>,
+    pattern: &str,
+    key: Option<&str>,
+    limit: usize,
+    deadline: Instant,
+) -> Result<Vec<parser::AuditLogResponse>> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let finished = Arc::new(AtomicBool::new(false));
+    let _guard = ProgressHandlerGuard(finished.clone());
+    conn.lock_handle()
+        .await?
+        .set_progress_handler(1000, move || {
+            finished.load(Ordering::Relaxed) || Instant::now() < deadline
+        });
+
+    let mut audit_logs = Vec::new();
+    let result = {
+        let mut rows = sqlx::query_as::<_, parser::AuditLogResponse>(
+            "SELECT id, timestamp, command, key, success FROM audit_log WHERE command REGEXP ? AND (? IS NULL OR id IN (SELECT audit_log_id FROM audit_log_key WHERE key = ?)) ORDER BY timestamp DESC",
+        )
+        .bind(pattern)
+        .bind(key)
+        .bind(key)
+        .fetch(&mut *conn);
+
+        loop {
+            if audit_logs.len() >= limit {
+                break Ok(());
+            }
+            match rows.try_next().await {
+                Ok(Some(audit_log)) => audit_logs.push(audit_log),
+                Ok(None) => break Ok(()),
+                Err(err) => break Err(err),
+            }
+        }
+    };
+
+    if let Ok(mut handle) = conn.lock_handle().await {
+        handle.remove_progress_handler();
+    }
+
+    match result {
+        Ok(()) => Ok(audit_logs),
+        Err(_) if Instant::now() >= deadline => Err(RegexSearchTimeout.into()),
+        Err(err) => Err(anyhow!("ERROR: Could not regex search audit_logs: {err}")),
+    }
 }
 
 pub async fn fetch_last_audit_log(pool: &Pool<Sqlite>) -> Result<Option<parser::AuditLogResponse>> {
@@ -98,7 +613,7 @@ pub async fn fetch_last_audit_log(pool: &Pool<Sqlite>) -> Result<Option<parser::
 
     let last_audit_log = match query_as!(
         parser::AuditLogResponse,
-        "SELECT timestamp, command FROM audit_log ORDER BY timestamp DESC LIMIT 1"
+        "SELECT id as \"id!\", timestamp, command, key, success FROM audit_log ORDER BY timestamp DESC LIMIT 1"
     )
     .fetch_optional(&mut *conn)
     .await
@@ -114,56 +629,1934 @@ pub async fn fetch_last_audit_log(pool: &Pool<Sqlite>) -> Result<Option<parser::
     Ok(last_audit_log)
 }
 
+/// Inserts `new_log` at the end of the hash chain, unless an exec with the same host,
+/// timestamp and serial is already stored, in which case no row is affected.
 pub async fn insert_audit_log(
     pool: &Pool<Sqlite>,
     new_log: &parser::AuditLog,
 ) -> Result<SqliteQueryResult> {
-    let mut conn = get_connection(pool)
-        .await
-        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(err) => return Err(anyhow!("ERROR: Could not start transaction: {err}")),
+    };
 
-    let args = new_log.get_args_string();
+    let prev_hash = match query_scalar!("SELECT hash FROM chain_head WHERE id = 1")
+        .fetch_optional(&mut *tx)
+        .await
+    {
+        Ok(hash) => hash.unwrap_or(chain::GENESIS_HASH.to_string()),
+        Err(err) => return Err(anyhow!("ERROR: Could not fetch chain head: {err}")),
+    };
+    let fields = chain::ChainFields::of(new_log);
+    let hash = fields.hash(&prev_hash);
 
-    let query = query_as!(
-        AuditLog,
-        "INSERT INTO audit_log (log_type, timestamp, program, args, argc, command) VALUES (?, ?, ?, ?, ?, ?)",
-        new_log.log_type,
-        new_log.timestamp,
-        new_log.program,
-        args,
-        new_log.argc,
-        new_log.command,
+    let query = query!(
+        "INSERT OR IGNORE INTO audit_log (log_type, timestamp, program, args, argc, command, serial, host, exe, pid, ppid, uid, user, ses, tty, auid, login_user, key, success, exit, raw, original, hash, prev_hash) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        fields.log_type,
+        fields.timestamp,
+        fields.program,
+        fields.args,
+        fields.argc,
+        fields.command,
+        fields.serial,
+        fields.host,
+        fields.exe,
+        fields.pid,
+        fields.ppid,
+        fields.uid,
+        fields.user,
+        fields.ses,
+        fields.tty,
+        fields.auid,
+        fields.login_user,
+        fields.key,
+        fields.success,
+        fields.exit,
+        fields.raw,
+        fields.original,
+        hash,
+        prev_hash,
     );
 
-    let result = match query.execute(&mut *conn).await {
+    let result = match query.execute(&mut *tx).await {
         Ok(query_result) => query_result,
         Err(err) => {
             return Err(anyhow!("ERROR: Could not create audit_log: {err}"));
         }
     };
+    if result.rows_affected() == 0 {
+        return Ok(result);
+    }
+
+    let id = result.last_insert_rowid();
+    for key in split_keys(fields.key.as_deref()) {
+        let query = query!(
+            "INSERT OR IGNORE INTO audit_log_key (audit_log_id, key) VALUES (?, ?)",
+            id,
+            key,
+        );
+        if let Err(err) = query.execute(&mut *tx).await {
+            return Err(anyhow!("ERROR: Could not create audit_log_key: {err}"));
+        }
+    }
+
+    let query = query!(
+        "INSERT INTO chain_head (id, audit_log_id, hash) VALUES (1, ?, ?) ON CONFLICT (id) DO UPDATE SET audit_log_id = excluded.audit_log_id, hash = excluded.hash",
+        id,
+        hash,
+    );
+    if let Err(err) = query.execute(&mut *tx).await {
+        return Err(anyhow!("ERROR: Could not update chain head: {err}"));
+    }
+
+    if let Err(err) = tx.commit().await {
+        return Err(anyhow!("ERROR: Could not create audit_log: {err}"));
+    }
 
     Ok(result)
 }
 
-pub async fn clear_audit_logs(pool: &Pool<Sqlite>) -> Result<SqliteQueryResult> {
+/// Rows a deletion would remove, used to confirm it before it runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::FromRow)]
+pub struct DeletionPreview {
+    pub count: i64,
+    pub max_id: Option<i64>,
+}
+
+impl DeletionPreview {
+    fn confirmation_mac(
+        &self,
+        filter: &AuditLogFilter,
+        issued_at: i64,
+        secret: &[u8],
+    ) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC takes keys of any size");
+        mac.update(serde_json::to_string(filter).unwrap_or_default().as_bytes());
+        mac.update(format!("\n{}\n{:?}\n{issued_at}", self.count, self.max_id).as_bytes());
+        mac
+    }
+
+    /// Token confirming a deletion of exactly these rows, as `<issued at>.<HMAC>` keyed
+    /// with a server secret.
+    pub fn confirmation_token(
+        &self,
+        filter: &AuditLogFilter,
+        issued_at: i64,
+        secret: &[u8],
+    ) -> String {
+        let mac = self.confirmation_mac(filter, issued_at, secret).finalize();
+        let mac: String = mac
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+
+        format!("{issued_at}.{mac}")
+    }
+
+    /// Checks a token issued at most `ttl_secs` before `now` for the same rows.
+    pub fn verify(
+        &self,
+        filter: &AuditLogFilter,
+        token: &str,
+        now: i64,
+        ttl_secs: i64,
+        secret: &[u8],
+    ) -> bool {
+        let Some((issued_at, mac)) = token.split_once('.') else {
+            return false;
+        };
+        let Ok(issued_at) = issued_at.parse::<i64>() else {
+            return false;
+        };
+        let Some(mac) = (0..mac.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(mac.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<u8>>>()
+        else {
+            return false;
+        };
+
+        (issued_at..=issued_at + ttl_secs).contains(&now)
+            && self
+                .confirmation_mac(filter, issued_at, secret)
+                .verify_slice(&mac)
+                .is_ok()
+    }
+}
+
+pub async fn preview_deletion(
+    pool: &Pool<Sqlite>,
+    filter: &AuditLogFilter,
+) -> Result<DeletionPreview> {
     let mut conn = get_connection(pool)
         .await
         .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
 
-    let two_weeks_ago = chrono::Utc::now() - chrono::Duration::weeks(2);
-    let two_weeks_ago_str = two_weeks_ago.to_rfc3339();
+    let mut builder =
+        QueryBuilder::new("SELECT COUNT(*) AS count, MAX(id) AS max_id FROM audit_log WHERE 1 = 1");
+    filter.push_conditions(&mut builder);
+
+    let preview = match builder
+        .build_query_as::<DeletionPreview>()
+        .fetch_one(&mut *conn)
+        .await
+    {
+        Ok(query_result) => query_result,
+        Err(err) => {
+            return Err(anyhow!(
+                "ERROR: Could not preview audit_log deletion: {err}"
+            ));
+        }
+    };
+
+    Ok(preview)
+}
+
+/// Entry of the audit trail kept for every deletion.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct AuditLogDeletion {
+    pub id: i64,
+    pub deleted_at: String,
+    pub actor: Option<String>,
+    pub remote_addr: Option<String>,
+    pub filters: String,
+    pub count: i64,
+}
+
+/// Deletes the rows matching `filter` up to `max_id` and records who did it.
+///
+/// Returns the ids of the deleted rows so callers can drop them from the search index.
+pub async fn delete_audit_logs(
+    pool: &Pool<Sqlite>,
+    filter: &AuditLogFilter,
+    max_id: i64,
+    actor: Option<&str>,
+    remote_addr: Option<&str>,
+) -> Result<Vec<i64>> {
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(err) => return Err(anyhow!("ERROR: Could not start transaction: {err}")),
+    };
+
+    let mut builder = QueryBuilder::new("DELETE FROM audit_log WHERE id <= ");
+    builder.push_bind(max_id);
+    filter.push_conditions(&mut builder);
+    builder.push(" RETURNING id, hash, prev_hash");
+
+    let deleted = match builder
+        .build_query_as::<(i64, Option<String>, Option<String>)>()
+        .fetch_all(&mut *tx)
+        .await
+    {
+        Ok(query_result) => query_result,
+        Err(err) => {
+            return Err(anyhow!("ERROR: Could not delete audit_logs: {err}"));
+        }
+    };
+    let ids: Vec<i64> = deleted.iter().map(|(id, ..)| *id).collect();
 
+    let deleted_at = Utc::now();
+    let filters = serde_json::to_string(filter)?;
+    let count = ids.len() as i64;
     let query = query!(
-        "DELETE FROM audit_log WHERE timestamp < ?",
-        two_weeks_ago_str
+        "INSERT INTO audit_log_deletion (deleted_at, actor, remote_addr, filters, count) VALUES (?, ?, ?, ?, ?)",
+        deleted_at,
+        actor,
+        remote_addr,
+        filters,
+        count,
     );
+    let deletion_id = match query.execute(&mut *tx).await {
+        Ok(query_result) => query_result.last_insert_rowid(),
+        Err(err) => {
+            return Err(anyhow!("ERROR: Could not record audit_log deletion: {err}"));
+        }
+    };
+
+    // Keeps the place of the deleted rows in the hash chain.
+    for (id, hash, prev_hash) in deleted {
+        let query = query!(
+            "INSERT INTO audit_log_tombstone (id, hash, prev_hash, deletion_id) VALUES (?, ?, ?, ?)",
+            id,
+            hash,
+            prev_hash,
+            deletion_id,
+        );
+        if let Err(err) = query.execute(&mut *tx).await {
+            return Err(anyhow!(
+                "ERROR: Could not record deleted audit_log {id}: {err}"
+            ));
+        }
+    }
+
+    if let Err(err) = tx.commit().await {
+        return Err(anyhow!("ERROR: Could not delete audit_logs: {err}"));
+    }
+
+    Ok(ids)
+}
+
+pub async fn fetch_audit_log_deletions(
+    pool: &Pool<Sqlite>,
+    limit: i64,
+) -> Result<Vec<AuditLogDeletion>> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
 
-    let result = match query.execute(&mut *conn).await {
+    let deletions = match query_as!(
+        AuditLogDeletion,
+        "SELECT id, deleted_at, actor, remote_addr, filters, count FROM audit_log_deletion ORDER BY id DESC LIMIT ?",
+        limit
+    )
+    .fetch_all(&mut *conn)
+    .await
+    {
         Ok(query_result) => query_result,
         Err(err) => {
-            return Err(anyhow!("ERROR: Could not clear audit_logs: {err}"));
+            return Err(anyhow!("ERROR: Could not fetch audit_log deletions: {err}"));
         }
     };
 
-    Ok(result)
+    Ok(deletions)
+}
+
+const CHAIN_ENTRIES_QUERY: &str = "SELECT * FROM (SELECT id, hash, prev_hash, FALSE AS deleted, log_type, timestamp, program, args, argc, command, serial, host, exe, pid, ppid, uid, user, ses, tty, auid, login_user, key, success, exit, raw, original FROM audit_log UNION ALL SELECT id, hash, prev_hash, TRUE, '', '', '', '', 0, '', NULL, NULL, NULL, NULL, NULL, NULL, NULL, NULL, NULL, NULL, NULL, NULL, NULL, NULL, NULL, NULL FROM audit_log_tombstone)";
+
+/// Up to `limit` rows of the hash chain after `after_id`, deleted ones included.
+pub async fn fetch_chain_entries(
+    pool: &Pool<Sqlite>,
+    after_id: i64,
+    limit: i64,
+) -> Result<Vec<chain::ChainEntry>> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let mut builder = QueryBuilder::new(CHAIN_ENTRIES_QUERY);
+    builder.push(" WHERE id > ").push_bind(after_id);
+    builder.push(" ORDER BY id LIMIT ").push_bind(limit);
+
+    match builder
+        .build_query_as::<chain::ChainEntry>()
+        .fetch_all(&mut *conn)
+        .await
+    {
+        Ok(entries) => Ok(entries),
+        Err(err) => Err(anyhow!("ERROR: Could not fetch hash chain: {err}")),
+    }
+}
+
+pub async fn fetch_chain_head(pool: &Pool<Sqlite>) -> Result<Option<chain::ChainHead>> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    match query_as!(
+        chain::ChainHead,
+        "SELECT audit_log_id, hash FROM chain_head WHERE id = 1"
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    {
+        Ok(head) => Ok(head),
+        Err(err) => Err(anyhow!("ERROR: Could not fetch chain head: {err}")),
+    }
+}
+
+/// Chains the rows stored before the hash chain existed, returning how many there were.
+pub async fn seal_audit_logs(pool: &Pool<Sqlite>) -> Result<u64> {
+    if fetch_chain_head(pool).await?.is_some() {
+        return Ok(0);
+    }
+
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(err) => return Err(anyhow!("ERROR: Could not start transaction: {err}")),
+    };
+
+    // Sealing again would hide whatever removed the head, see `chain::verify`.
+    let mut builder = QueryBuilder::new(CHAIN_ENTRIES_QUERY);
+    builder.push(" WHERE hash IS NOT NULL LIMIT 1");
+    match builder
+        .build_query_as::<chain::ChainEntry>()
+        .fetch_optional(&mut *tx)
+        .await
+    {
+        Ok(None) => {}
+        Ok(Some(_)) => {
+            return Err(anyhow!(
+                "ERROR: Chain head is missing while audit logs are chained, run `auditor verify`"
+            ))
+        }
+        Err(err) => return Err(anyhow!("ERROR: Could not fetch chain entries: {err}")),
+    }
+
+    let mut builder = QueryBuilder::new(CHAIN_ENTRIES_QUERY);
+    builder.push(" WHERE hash IS NULL ORDER BY id");
+    let mut entries = builder
+        .build_query_as::<chain::ChainEntry>()
+        .fetch(&mut *tx);
+    let mut sealed = Vec::new();
+    let mut prev_hash = chain::GENESIS_HASH.to_string();
+    while let Some(entry) = entries.try_next().await? {
+        let hash = entry.fields.hash(&prev_hash);
+        sealed.push((entry.id, hash.clone(), prev_hash));
+        prev_hash = hash;
+    }
+    drop(entries);
+
+    let Some((head_id, head_hash, _)) = sealed.last().cloned() else {
+        return Ok(0);
+    };
+    for (id, hash, prev_hash) in &sealed {
+        let query = query!(
+            "UPDATE audit_log SET hash = ?, prev_hash = ? WHERE id = ?",
+            hash,
+            prev_hash,
+            id,
+        );
+        if let Err(err) = query.execute(&mut *tx).await {
+            return Err(anyhow!("ERROR: Could not chain audit_log {id}: {err}"));
+        }
+    }
+
+    let query = query!(
+        "INSERT INTO chain_head (id, audit_log_id, hash) VALUES (1, ?, ?)",
+        head_id,
+        head_hash,
+    );
+    if let Err(err) = query.execute(&mut *tx).await {
+        return Err(anyhow!("ERROR: Could not update chain head: {err}"));
+    }
+
+    if let Err(err) = tx.commit().await {
+        return Err(anyhow!("ERROR: Could not chain audit_logs: {err}"));
+    }
+
+    Ok(sealed.len() as u64)
+}
+
+pub async fn insert_checkpoint(
+    pool: &Pool<Sqlite>,
+    created_at: &str,
+    head: &chain::ChainHead,
+    public_key: &str,
+    signature: &str,
+) -> Result<chain::Checkpoint> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    match query_as!(
+        chain::Checkpoint,
+        "INSERT INTO chain_checkpoint (created_at, audit_log_id, hash, public_key, signature) VALUES (?, ?, ?, ?, ?) RETURNING id, created_at, audit_log_id, hash, public_key, signature",
+        created_at,
+        head.audit_log_id,
+        head.hash,
+        public_key,
+        signature,
+    )
+    .fetch_one(&mut *conn)
+    .await
+    {
+        Ok(checkpoint) => Ok(checkpoint),
+        Err(err) => Err(anyhow!("ERROR: Could not create chain checkpoint: {err}")),
+    }
+}
+
+/// Every checkpoint, oldest first.
+pub async fn fetch_checkpoints(pool: &Pool<Sqlite>) -> Result<Vec<chain::Checkpoint>> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    match query_as!(
+        chain::Checkpoint,
+        "SELECT id, created_at, audit_log_id, hash, public_key, signature FROM chain_checkpoint ORDER BY id"
+    )
+    .fetch_all(&mut *conn)
+    .await
+    {
+        Ok(checkpoints) => Ok(checkpoints),
+        Err(err) => Err(anyhow!("ERROR: Could not fetch chain checkpoints: {err}")),
+    }
+}
+
+/// Id of the latest checkpoint ever stored, 0 before the first.
+pub async fn fetch_last_checkpoint_id(pool: &Pool<Sqlite>) -> Result<i64> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    match query_scalar!(
+        "SELECT CAST(seq AS INTEGER) AS \"seq!: i64\" FROM sqlite_sequence WHERE name = 'chain_checkpoint'"
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    {
+        Ok(seq) => Ok(seq.unwrap_or_default()),
+        Err(err) => Err(anyhow!("ERROR: Could not fetch last checkpoint id: {err}")),
+    }
+}
+
+/// Stored user together with its password hash, only used to authenticate.
+#[derive(Debug)]
+pub struct UserRow {
+    pub id: i64,
+    pub username: String,
+    pub password_hash: String,
+    pub role: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApiToken {
+    pub id: i64,
+    pub name: String,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+}
+
+pub async fn count_users(pool: &Pool<Sqlite>) -> Result<i64> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    match query_scalar!("SELECT COUNT(*) FROM user")
+        .fetch_one(&mut *conn)
+        .await
+    {
+        Ok(count) => Ok(count),
+        Err(err) => Err(anyhow!("ERROR: Could not count users: {err}")),
+    }
+}
+
+pub async fn insert_user(
+    pool: &Pool<Sqlite>,
+    username: &str,
+    password_hash: &str,
+    role: &str,
+) -> Result<i64> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let created_at = Utc::now();
+    let query = query!(
+        "INSERT INTO user (username, password_hash, role, created_at) VALUES (?, ?, ?, ?)",
+        username,
+        password_hash,
+        role,
+        created_at,
+    );
+
+    match query.execute(&mut *conn).await {
+        Ok(query_result) => Ok(query_result.last_insert_rowid()),
+        Err(err) => Err(anyhow!("ERROR: Could not create user '{username}': {err}")),
+    }
+}
+
+pub async fn fetch_users(pool: &Pool<Sqlite>) -> Result<Vec<UserRow>> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    match query_as!(
+        UserRow,
+        "SELECT id as \"id!\", username, password_hash, role FROM user ORDER BY username"
+    )
+    .fetch_all(&mut *conn)
+    .await
+    {
+        Ok(users) => Ok(users),
+        Err(err) => Err(anyhow!("ERROR: Could not fetch users: {err}")),
+    }
+}
+
+pub async fn fetch_user_by_username(
+    pool: &Pool<Sqlite>,
+    username: &str,
+) -> Result<Option<UserRow>> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    match query_as!(
+        UserRow,
+        "SELECT id as \"id!\", username, password_hash, role FROM user WHERE username = ?",
+        username
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    {
+        Ok(user) => Ok(user),
+        Err(err) => Err(anyhow!("ERROR: Could not fetch user '{username}': {err}")),
+    }
+}
+
+pub async fn insert_session(
+    pool: &Pool<Sqlite>,
+    token_hash: &str,
+    user_id: i64,
+    expires_at: DateTime<Utc>,
+) -> Result<()> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let now = Utc::now();
+    if let Err(err) = query!("DELETE FROM session WHERE expires_at <= ?", now)
+        .execute(&mut *conn)
+        .await
+    {
+        return Err(anyhow!("ERROR: Could not delete expired sessions: {err}"));
+    }
+
+    let query = query!(
+        "INSERT INTO session (token_hash, user_id, created_at, expires_at) VALUES (?, ?, ?, ?)",
+        token_hash,
+        user_id,
+        now,
+        expires_at,
+    );
+
+    match query.execute(&mut *conn).await {
+        Ok(_) => Ok(()),
+        Err(err) => Err(anyhow!("ERROR: Could not create session: {err}")),
+    }
+}
+
+pub async fn fetch_session_user(pool: &Pool<Sqlite>, token_hash: &str) -> Result<Option<UserRow>> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let now = Utc::now();
+    match query_as!(
+        UserRow,
+        "SELECT user.id as \"id!\", user.username, user.password_hash, user.role FROM session JOIN user ON user.id = session.user_id WHERE session.token_hash = ? AND session.expires_at > ?",
+        token_hash,
+        now
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    {
+        Ok(user) => Ok(user),
+        Err(err) => Err(anyhow!("ERROR: Could not fetch session: {err}")),
+    }
+}
+
+pub async fn delete_session(pool: &Pool<Sqlite>, token_hash: &str) -> Result<()> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    match query!("DELETE FROM session WHERE token_hash = ?", token_hash)
+        .execute(&mut *conn)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(err) => Err(anyhow!("ERROR: Could not delete session: {err}")),
+    }
+}
+
+pub async fn insert_api_token(
+    pool: &Pool<Sqlite>,
+    user_id: i64,
+    name: &str,
+    token_hash: &str,
+) -> Result<ApiToken> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let created_at = Utc::now();
+    let query = query!(
+        "INSERT INTO api_token (user_id, name, token_hash, created_at) VALUES (?, ?, ?, ?)",
+        user_id,
+        name,
+        token_hash,
+        created_at,
+    );
+
+    match query.execute(&mut *conn).await {
+        Ok(query_result) => Ok(ApiToken {
+            id: query_result.last_insert_rowid(),
+            name: name.to_string(),
+            created_at: created_at.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, false),
+            last_used_at: None,
+        }),
+        Err(err) => Err(anyhow!("ERROR: Could not create api_token: {err}")),
+    }
+}
+
+pub async fn fetch_api_tokens(pool: &Pool<Sqlite>, user_id: i64) -> Result<Vec<ApiToken>> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    match query_as!(
+        ApiToken,
+        "SELECT id, name, created_at, last_used_at FROM api_token WHERE user_id = ? ORDER BY id",
+        user_id
+    )
+    .fetch_all(&mut *conn)
+    .await
+    {
+        Ok(tokens) => Ok(tokens),
+        Err(err) => Err(anyhow!("ERROR: Could not fetch api_tokens: {err}")),
+    }
+}
+
+/// Looks up the owner of an API token and marks the token as used.
+pub async fn fetch_api_token_user(
+    pool: &Pool<Sqlite>,
+    token_hash: &str,
+) -> Result<Option<UserRow>> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let user = match query_as!(
+        UserRow,
+        "SELECT user.id as \"id!\", user.username, user.password_hash, user.role FROM api_token JOIN user ON user.id = api_token.user_id WHERE api_token.token_hash = ?",
+        token_hash
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    {
+        Ok(user) => user,
+        Err(err) => {
+            return Err(anyhow!("ERROR: Could not fetch api_token: {err}"));
+        }
+    };
+
+    if user.is_some() {
+        let now = Utc::now();
+        if let Err(err) = query!(
+            "UPDATE api_token SET last_used_at = ? WHERE token_hash = ?",
+            now,
+            token_hash
+        )
+        .execute(&mut *conn)
+        .await
+        {
+            return Err(anyhow!("ERROR: Could not update api_token: {err}"));
+        }
+    }
+
+    Ok(user)
+}
+
+/// Deletes one of the tokens of `user_id`, returning whether it existed.
+pub async fn delete_api_token(pool: &Pool<Sqlite>, id: i64, user_id: i64) -> Result<bool> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    match query!(
+        "DELETE FROM api_token WHERE id = ? AND user_id = ?",
+        id,
+        user_id
+    )
+    .execute(&mut *conn)
+    .await
+    {
+        Ok(query_result) => Ok(query_result.rows_affected() > 0),
+        Err(err) => Err(anyhow!("ERROR: Could not delete api_token {id}: {err}")),
+    }
+}
+
+pub async fn fetch_top_counts(
+    pool: &Pool<Sqlite>,
+    dimension: stats::TopDimension,
+    filter: &AuditLogFilter,
+    n: i64,
+) -> Result<Vec<stats::TopEntry>> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let column = dimension.column();
+    let mut builder = QueryBuilder::new(format!(
+        "SELECT {column} AS key, COUNT(*) AS count FROM (SELECT * FROM audit_log WHERE 1 = 1"
+    ));
+    filter.push_conditions(&mut builder);
+    builder
+        .push(") AS audit_log")
+        .push(dimension.join())
+        .push(" GROUP BY 1 ORDER BY count DESC, key ASC LIMIT ")
+        .push_bind(n);
+
+    match builder
+        .build_query_as::<stats::TopEntry>()
+        .fetch_all(&mut *conn)
+        .await
+    {
+        Ok(query_result) => Ok(query_result),
+        Err(err) => Err(anyhow!("ERROR: Could not fetch top {column}: {err}")),
+    }
+}
+
+/// Counts rows per bucket, see [`stats::fill_histogram`] to fill the gaps.
+pub async fn fetch_histogram_counts(
+    pool: &Pool<Sqlite>,
+    bucket: stats::HistogramBucket,
+    filter: &AuditLogFilter,
+) -> Result<Vec<(String, i64)>> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    // Timestamps are stored as UTC RFC 3339, so buckets are plain prefixes.
+    let mut builder = QueryBuilder::new("SELECT substr(timestamp, 1, ");
+    builder
+        .push_bind(bucket.prefix_len())
+        .push(") AS bucket, COUNT(*) AS count FROM audit_log WHERE 1 = 1");
+    filter.push_conditions(&mut builder);
+    builder.push(" GROUP BY bucket ORDER BY bucket");
+
+    match builder
+        .build_query_as::<(String, i64)>()
+        .fetch_all(&mut *conn)
+        .await
+    {
+        Ok(query_result) => Ok(query_result),
+        Err(err) => Err(anyhow!("ERROR: Could not fetch histogram: {err}")),
+    }
+}
+
+pub async fn fetch_summary(pool: &Pool<Sqlite>, filter: &AuditLogFilter) -> Result<stats::Summary> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let mut builder = QueryBuilder::new(
+        "SELECT COUNT(*) AS total, COUNT(DISTINCT program) AS programs, COUNT(DISTINCT COALESCE(user, uid)) AS users, COUNT(DISTINCT host) AS hosts, MIN(timestamp) AS first_seen, MAX(timestamp) AS last_seen FROM audit_log WHERE 1 = 1",
+    );
+    filter.push_conditions(&mut builder);
+
+    match builder
+        .build_query_as::<stats::Summary>()
+        .fetch_one(&mut *conn)
+        .await
+    {
+        Ok(query_result) => Ok(query_result),
+        Err(err) => Err(anyhow!("ERROR: Could not fetch summary: {err}")),
+    }
+}
+
+const PROCESS_EVENT_COLUMNS: &str = "id, timestamp, command, host, pid, ppid, user";
+
+pub async fn fetch_process_event(
+    pool: &Pool<Sqlite>,
+    id: i64,
+) -> Result<Option<tree::ProcessEvent>> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    match query_as!(
+        tree::ProcessEvent,
+        "SELECT id, timestamp, command, host, pid, ppid, user FROM audit_log WHERE id = ?",
+        id
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    {
+        Ok(event) => Ok(event),
+        Err(err) => Err(anyhow!("ERROR: Could not fetch audit_log {id}: {err}")),
+    }
+}
+
+/// Latest exec before `event` whose pid is the ppid of `event`, on the same host.
+pub async fn fetch_process_parent(
+    pool: &Pool<Sqlite>,
+    event: &tree::ProcessEvent,
+) -> Result<Option<tree::ProcessEvent>> {
+    let Some(ppid) = event.ppid else {
+        return Ok(None);
+    };
+
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let mut builder = QueryBuilder::new(format!(
+        "SELECT {PROCESS_EVENT_COLUMNS} FROM audit_log WHERE host IS "
+    ));
+    builder
+        .push_bind(event.host.clone())
+        .push(" AND pid = ")
+        .push_bind(ppid)
+        .push(" AND (timestamp, id) < (")
+        .push_bind(event.timestamp.clone())
+        .push(", ")
+        .push_bind(event.id)
+        .push(") ORDER BY timestamp DESC, id DESC LIMIT 1");
+
+    match builder
+        .build_query_as::<tree::ProcessEvent>()
+        .fetch_optional(&mut *conn)
+        .await
+    {
+        Ok(parent) => Ok(parent),
+        Err(err) => Err(anyhow!(
+            "ERROR: Could not fetch parent of audit_log {id}: {err}",
+            id = event.id
+        )),
+    }
+}
+
+/// Execs whose ppid is the pid of `event`, until that pid execs something else.
+pub async fn fetch_process_children(
+    pool: &Pool<Sqlite>,
+    event: &tree::ProcessEvent,
+    limit: i64,
+) -> Result<Vec<tree::ProcessEvent>> {
+    let Some(pid) = event.pid else {
+        return Ok(Vec::new());
+    };
+
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let mut builder = QueryBuilder::new(format!(
+        "SELECT {PROCESS_EVENT_COLUMNS} FROM audit_log WHERE host IS "
+    ));
+    builder
+        .push_bind(event.host.clone())
+        .push(" AND ppid = ")
+        .push_bind(pid)
+        .push(" AND (timestamp, id) > (")
+        .push_bind(event.timestamp.clone())
+        .push(", ")
+        .push_bind(event.id)
+        .push(")");
+
+    // The next exec of the same pid replaces this process image.
+    builder
+        .push(" AND (timestamp, id) < (SELECT COALESCE(MAX(timestamp), '9999'), COALESCE(MAX(id), 0) FROM (SELECT timestamp, id FROM audit_log WHERE host IS ")
+        .push_bind(event.host.clone())
+        .push(" AND pid = ")
+        .push_bind(pid)
+        .push(" AND (timestamp, id) > (")
+        .push_bind(event.timestamp.clone())
+        .push(", ")
+        .push_bind(event.id)
+        .push(") ORDER BY timestamp, id LIMIT 1))")
+        .push(" ORDER BY timestamp, id LIMIT ")
+        .push_bind(limit);
+
+    match builder
+        .build_query_as::<tree::ProcessEvent>()
+        .fetch_all(&mut *conn)
+        .await
+    {
+        Ok(children) => Ok(children),
+        Err(err) => Err(anyhow!(
+            "ERROR: Could not fetch children of audit_log {id}: {err}",
+            id = event.id
+        )),
+    }
+}
+
+/// Id of the latest login, session start or end on the host of an exec with its audit
+/// session id, or 0 before any was recorded.
+const SESSION_LOGIN: &str = "COALESCE((SELECT e.id FROM auth_event e WHERE e.host IS audit_log.host AND e.ses = audit_log.ses AND e.record_type IN ('USER_LOGIN', 'USER_START', 'USER_END') AND e.timestamp <= audit_log.timestamp ORDER BY e.timestamp DESC, e.id DESC LIMIT 1), 0)";
+
+const SESSION_COLUMNS: &str = "CAST(ses AS TEXT) || ':' || login || ':' || COALESCE(tty, '') || COALESCE('@' || host, '') AS id, host, ses, tty, MIN(auid) AS auid, MAX(login_user) AS login_user, (SELECT auth_event.addr FROM auth_event WHERE auth_event.id = login) AS source, MIN(timestamp) AS start, MAX(timestamp) AS end, COUNT(*) AS commands";
+
+const SESSION_GROUP: &str = " GROUP BY host, ses, login, tty";
+
+/// Execs with a session along with the login each belongs to, to select `SESSION_COLUMNS` from.
+fn push_session_execs(builder: &mut QueryBuilder<'_, Sqlite>, filter: &AuditLogFilter) {
+    builder.push(format!(
+        " FROM (SELECT audit_log.*, {SESSION_LOGIN} AS login FROM audit_log WHERE ses IS NOT NULL"
+    ));
+    filter.push_conditions(builder);
+    builder.push(")");
+}
+
+/// Sessions with at least one command matching `filter`, most recently active first.
+pub async fn fetch_sessions(
+    pool: &Pool<Sqlite>,
+    filter: &AuditLogFilter,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<session::Session>> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let mut builder = QueryBuilder::new(format!("SELECT {SESSION_COLUMNS}"));
+    push_session_execs(&mut builder, filter);
+    builder
+        .push(SESSION_GROUP)
+        .push(" ORDER BY end DESC, id LIMIT ")
+        .push_bind(limit)
+        .push(" OFFSET ")
+        .push_bind(offset);
+
+    match builder
+        .build_query_as::<session::Session>()
+        .fetch_all(&mut *conn)
+        .await
+    {
+        Ok(sessions) => Ok(sessions),
+        Err(err) => Err(anyhow!("ERROR: Could not fetch sessions: {err}")),
+    }
+}
+
+pub async fn count_sessions(pool: &Pool<Sqlite>, filter: &AuditLogFilter) -> Result<i64> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let mut builder = QueryBuilder::new("SELECT COUNT(*) FROM (SELECT 1");
+    push_session_execs(&mut builder, filter);
+    builder.push(SESSION_GROUP).push(")");
+
+    match builder
+        .build_query_scalar::<i64>()
+        .fetch_one(&mut *conn)
+        .await
+    {
+        Ok(count) => Ok(count),
+        Err(err) => Err(anyhow!("ERROR: Could not count sessions: {err}")),
+    }
+}
+
+pub async fn fetch_session(
+    pool: &Pool<Sqlite>,
+    key: &session::SessionKey,
+) -> Result<Option<session::SessionDetail>> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let push_execs = |builder: &mut QueryBuilder<'_, Sqlite>| {
+        builder
+            .push(format!(
+                " FROM (SELECT audit_log.*, {SESSION_LOGIN} AS login FROM audit_log WHERE ses = "
+            ))
+            .push_bind(key.ses)
+            .push(" AND host IS ")
+            .push_bind(key.host.clone())
+            .push(") WHERE login = ")
+            .push_bind(key.login)
+            .push(" AND tty IS ")
+            .push_bind(key.tty.clone());
+    };
+
+    let mut builder = QueryBuilder::new(format!("SELECT {SESSION_COLUMNS}"));
+    push_execs(&mut builder);
+    builder.push(SESSION_GROUP);
+
+    let session = match builder
+        .build_query_as::<session::Session>()
+        .fetch_optional(&mut *conn)
+        .await
+    {
+        Ok(Some(session)) => session,
+        Ok(None) => return Ok(None),
+        Err(err) => {
+            return Err(anyhow!("ERROR: Could not fetch session {}: {err}", key.ses));
+        }
+    };
+
+    let mut builder = QueryBuilder::new("SELECT id, timestamp, command, key, success");
+    push_execs(&mut builder);
+    builder
+        .push(" ORDER BY timestamp, id LIMIT ")
+        .push_bind(session::MAX_SESSION_COMMANDS);
+
+    let history = match builder
+        .build_query_as::<parser::AuditLogResponse>()
+        .fetch_all(&mut *conn)
+        .await
+    {
+        Ok(history) => history,
+        Err(err) => {
+            return Err(anyhow!(
+                "ERROR: Could not fetch history of session {}: {err}",
+                key.ses
+            ));
+        }
+    };
+
+    Ok(Some(session::SessionDetail { session, history }))
+}
+
+pub async fn insert_auth_event(
+    pool: &Pool<Sqlite>,
+    auth_event: &parser::AuthEvent,
+) -> Result<SqliteQueryResult> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let serial = auth_event.serial as i64;
+    let raw = auth_event.raw.join("\n");
+
+    // Re-reading a log after a restart yields the same records again.
+    match query!(
+        "INSERT OR IGNORE INTO auth_event (record_type, timestamp, serial, host, pid, uid, auid, ses, account, op, exe, hostname, addr, terminal, success, raw) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        auth_event.record_type,
+        auth_event.timestamp,
+        serial,
+        auth_event.host,
+        auth_event.pid,
+        auth_event.uid,
+        auth_event.auid,
+        auth_event.ses,
+        auth_event.account,
+        auth_event.op,
+        auth_event.exe,
+        auth_event.hostname,
+        auth_event.addr,
+        auth_event.terminal,
+        auth_event.success,
+        raw,
+    )
+    .execute(&mut *conn)
+    .await
+    {
+        Ok(result) => Ok(result),
+        Err(err) => Err(anyhow!("ERROR: Could not create auth_event: {err}")),
+    }
+}
+
+fn push_login_conditions(filter: &login::LoginFilter, builder: &mut QueryBuilder<'_, Sqlite>) {
+    if let Some(from) = filter.from {
+        builder.push(" AND timestamp >= ").push_bind(from);
+    }
+    if let Some(to) = filter.to {
+        builder.push(" AND timestamp < ").push_bind(to);
+    }
+    if let Some(record_type) = &filter.record_type {
+        builder
+            .push(" AND record_type = ")
+            .push_bind(record_type.to_uppercase());
+    }
+    if let Some(account) = &filter.account {
+        builder.push(" AND account = ").push_bind(account.clone());
+    }
+    if let Some(host) = &filter.host {
+        builder.push(" AND host = ").push_bind(host.clone());
+    }
+    if let Some(source) = &filter.source {
+        builder
+            .push(" AND (addr = ")
+            .push_bind(source.clone())
+            .push(" OR hostname = ")
+            .push_bind(source.clone())
+            .push(")");
+    }
+    if let Some(success) = filter.success {
+        builder.push(" AND success = ").push_bind(success);
+    }
+}
+
+/// Login and authentication events matching `filter`, newest first.
+pub async fn fetch_logins(
+    pool: &Pool<Sqlite>,
+    filter: &login::LoginFilter,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<login::Login>> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let mut builder = QueryBuilder::new(
+        "SELECT id, record_type, timestamp, host, pid, uid, auid, ses, account, op, exe, hostname, addr, terminal, success FROM auth_event WHERE 1 = 1",
+    );
+    push_login_conditions(filter, &mut builder);
+    builder
+        .push(" ORDER BY timestamp DESC, id DESC LIMIT ")
+        .push_bind(limit)
+        .push(" OFFSET ")
+        .push_bind(offset);
+
+    match builder
+        .build_query_as::<login::Login>()
+        .fetch_all(&mut *conn)
+        .await
+    {
+        Ok(logins) => Ok(logins),
+        Err(err) => Err(anyhow!("ERROR: Could not fetch logins: {err}")),
+    }
+}
+
+pub async fn count_logins(pool: &Pool<Sqlite>, filter: &login::LoginFilter) -> Result<i64> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let mut builder = QueryBuilder::new("SELECT COUNT(*) FROM auth_event WHERE 1 = 1");
+    push_login_conditions(filter, &mut builder);
+
+    match builder
+        .build_query_scalar::<i64>()
+        .fetch_one(&mut *conn)
+        .await
+    {
+        Ok(count) => Ok(count),
+        Err(err) => Err(anyhow!("ERROR: Could not count logins: {err}")),
+    }
+}
+
+pub async fn insert_user_command(
+    pool: &Pool<Sqlite>,
+    user_command: &parser::UserCommand,
+) -> Result<SqliteQueryResult> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let serial = user_command.serial as i64;
+    let raw = user_command.raw.join("\n");
+
+    match query!(
+        "INSERT OR IGNORE INTO sudo_command (timestamp, serial, host, pid, uid, auid, ses, login_user, cwd, command, program, terminal, success, raw) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        user_command.timestamp,
+        serial,
+        user_command.host,
+        user_command.pid,
+        user_command.uid,
+        user_command.auid,
+        user_command.ses,
+        user_command.login_user,
+        user_command.cwd,
+        user_command.command,
+        user_command.program,
+        user_command.terminal,
+        user_command.success,
+        raw,
+    )
+    .execute(&mut *conn)
+    .await
+    {
+        Ok(result) => Ok(result),
+        Err(err) => Err(anyhow!("ERROR: Could not create sudo_command: {err}")),
+    }
+}
+
+/// Links a just stored exec to the latest unlinked sudo command it ran for.
+///
+/// The command runs in a child of sudo, or a grandchild when sudo allocates a pty as it
+/// does by default since 1.9.14, so besides the pid an exec in the same session by the
+/// same login user of the path sudo resolved also matches.
+pub async fn link_user_command(
+    pool: &Pool<Sqlite>,
+    audit_log_id: i64,
+    audit_log: &parser::AuditLog,
+) -> Result<bool> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let since = audit_log.timestamp - sudo::SUDO_LINK_WINDOW;
+
+    match query!(
+        "UPDATE sudo_command SET audit_log_id = ? WHERE id = (SELECT id FROM sudo_command WHERE audit_log_id IS NULL AND success AND host IS ? AND timestamp <= ? AND timestamp >= ? AND (pid = ? OR pid = ? OR (ses = ? AND auid IS ? AND (program = ? OR program = ?))) ORDER BY timestamp DESC, id DESC LIMIT 1)",
+        audit_log_id,
+        audit_log.host,
+        audit_log.timestamp,
+        since,
+        audit_log.pid,
+        audit_log.ppid,
+        audit_log.ses,
+        audit_log.auid,
+        audit_log.exe,
+        audit_log.program,
+    )
+    .execute(&mut *conn)
+    .await
+    {
+        Ok(result) => Ok(result.rows_affected() > 0),
+        Err(err) => Err(anyhow!("ERROR: Could not link sudo_command: {err}")),
+    }
+}
+
+/// Sudo commands along with the user they ran as, ready for appending conditions.
+const SUDO_COMMANDS_QUERY: &str = "SELECT * FROM (SELECT s.id, s.timestamp, s.host, s.pid, s.uid, s.auid, s.ses, s.login_user, s.cwd, s.command, s.terminal, s.success, s.audit_log_id, a.uid AS run_as_uid, COALESCE(a.user, CASE WHEN a.uid = 0 THEN 'root' END) AS run_as_user, a.uid != COALESCE(s.auid, s.uid, -1) AS privilege_escalation FROM sudo_command s LEFT JOIN audit_log a ON a.id = s.audit_log_id) WHERE 1 = 1";
+
+fn push_sudo_conditions(filter: &sudo::SudoFilter, builder: &mut QueryBuilder<'_, Sqlite>) {
+    if let Some(from) = filter.from {
+        builder.push(" AND timestamp >= ").push_bind(from);
+    }
+    if let Some(to) = filter.to {
+        builder.push(" AND timestamp < ").push_bind(to);
+    }
+    if let Some(user) = &filter.user {
+        builder
+            .push(" AND (login_user = ")
+            .push_bind(user.clone())
+            .push(" OR CAST(COALESCE(auid, uid) AS TEXT) = ")
+            .push_bind(user.clone())
+            .push(")");
+    }
+    if let Some(run_as) = &filter.run_as {
+        builder
+            .push(" AND (run_as_user = ")
+            .push_bind(run_as.clone())
+            .push(" OR CAST(run_as_uid AS TEXT) = ")
+            .push_bind(run_as.clone())
+            .push(")");
+    }
+    if let Some(host) = &filter.host {
+        builder.push(" AND host = ").push_bind(host.clone());
+    }
+    if let Some(privilege_escalation) = filter.privilege_escalation {
+        builder
+            .push(" AND privilege_escalation = ")
+            .push_bind(privilege_escalation);
+    }
+}
+
+/// Sudo commands matching `filter`, newest first.
+pub async fn fetch_sudo_commands(
+    pool: &Pool<Sqlite>,
+    filter: &sudo::SudoFilter,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<sudo::SudoCommand>> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let mut builder = QueryBuilder::new(SUDO_COMMANDS_QUERY);
+    push_sudo_conditions(filter, &mut builder);
+    builder
+        .push(" ORDER BY timestamp DESC, id DESC LIMIT ")
+        .push_bind(limit)
+        .push(" OFFSET ")
+        .push_bind(offset);
+
+    match builder
+        .build_query_as::<sudo::SudoCommand>()
+        .fetch_all(&mut *conn)
+        .await
+    {
+        Ok(sudo_commands) => Ok(sudo_commands),
+        Err(err) => Err(anyhow!("ERROR: Could not fetch sudo commands: {err}")),
+    }
+}
+
+pub async fn count_sudo_commands(pool: &Pool<Sqlite>, filter: &sudo::SudoFilter) -> Result<i64> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let mut builder = QueryBuilder::new(format!("SELECT COUNT(*) FROM ({SUDO_COMMANDS_QUERY}"));
+    push_sudo_conditions(filter, &mut builder);
+    builder.push(")");
+
+    match builder
+        .build_query_scalar::<i64>()
+        .fetch_one(&mut *conn)
+        .await
+    {
+        Ok(count) => Ok(count),
+        Err(err) => Err(anyhow!("ERROR: Could not count sudo commands: {err}")),
+    }
+}
+
+/// Stores one row per path of a file event, returning how many were new.
+pub async fn insert_file_event(pool: &Pool<Sqlite>, file_event: &parser::FileEvent) -> Result<u64> {
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(err) => return Err(anyhow!("ERROR: Could not start transaction: {err}")),
+    };
+
+    let serial = file_event.serial as i64;
+    let raw = file_event.raw.join("\n");
+    let mut inserted = 0;
+    for path in &file_event.paths {
+        let query = query!(
+            "INSERT OR IGNORE INTO file_access (timestamp, serial, item, host, pid, ppid, uid, user, auid, login_user, ses, exe, syscall, path, nametype, operation, success, exit, key, raw) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            file_event.timestamp,
+            serial,
+            path.item,
+            file_event.host,
+            file_event.pid,
+            file_event.ppid,
+            file_event.uid,
+            file_event.user,
+            file_event.auid,
+            file_event.login_user,
+            file_event.ses,
+            file_event.exe,
+            file_event.syscall,
+            path.path,
+            path.nametype,
+            path.operation,
+            file_event.success,
+            file_event.exit,
+            file_event.key,
+            raw,
+        );
+        let result = match query.execute(&mut *tx).await {
+            Ok(result) => result,
+            Err(err) => return Err(anyhow!("ERROR: Could not create file_access: {err}")),
+        };
+        if result.rows_affected() == 0 {
+            continue;
+        }
+        inserted += result.rows_affected();
+
+        let id = result.last_insert_rowid();
+        for key in split_keys(file_event.key.as_deref()) {
+            let query = query!(
+                "INSERT OR IGNORE INTO file_access_key (file_access_id, key) VALUES (?, ?)",
+                id,
+                key,
+            );
+            if let Err(err) = query.execute(&mut *tx).await {
+                return Err(anyhow!("ERROR: Could not create file_access_key: {err}"));
+            }
+        }
+    }
+
+    if let Err(err) = tx.commit().await {
+        return Err(anyhow!("ERROR: Could not create file_access: {err}"));
+    }
+
+    Ok(inserted)
+}
+
+fn push_file_access_conditions(
+    filter: &file_access::FileAccessFilter,
+    builder: &mut QueryBuilder<'_, Sqlite>,
+) {
+    if let Some(from) = filter.from {
+        builder.push(" AND timestamp >= ").push_bind(from);
+    }
+    if let Some(to) = filter.to {
+        builder.push(" AND timestamp < ").push_bind(to);
+    }
+    if let Some(key) = &filter.key {
+        builder
+            .push(" AND id IN (SELECT file_access_id FROM file_access_key WHERE key = ")
+            .push_bind(key.clone())
+            .push(")");
+    }
+    if let Some(path) = &filter.path {
+        if path.ends_with('/') {
+            builder
+                .push(" AND substr(path, 1, length(")
+                .push_bind(path.clone())
+                .push(")) = ")
+                .push_bind(path.clone());
+        } else {
+            builder.push(" AND path = ").push_bind(path.clone());
+        }
+    }
+    if let Some(operation) = &filter.operation {
+        builder
+            .push(" AND operation = ")
+            .push_bind(operation.clone());
+    }
+    if let Some(user) = &filter.user {
+        builder
+            .push(" AND (user = ")
+            .push_bind(user.clone())
+            .push(" OR CAST(uid AS TEXT) = ")
+            .push_bind(user.clone())
+            .push(")");
+    }
+    if let Some(host) = &filter.host {
+        builder.push(" AND host = ").push_bind(host.clone());
+    }
+    if let Some(success) = filter.success {
+        builder.push(" AND success = ").push_bind(success);
+    }
+}
+
+/// File accesses matching `filter`, newest first.
+pub async fn fetch_file_accesses(
+    pool: &Pool<Sqlite>,
+    filter: &file_access::FileAccessFilter,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<file_access::FileAccess>> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let mut builder = QueryBuilder::new(
+        "SELECT id, timestamp, host, pid, ppid, uid, user, auid, login_user, ses, exe, syscall, path, nametype, operation, success, exit, key FROM file_access WHERE 1 = 1",
+    );
+    push_file_access_conditions(filter, &mut builder);
+    builder
+        .push(" ORDER BY timestamp DESC, id DESC LIMIT ")
+        .push_bind(limit)
+        .push(" OFFSET ")
+        .push_bind(offset);
+
+    match builder
+        .build_query_as::<file_access::FileAccess>()
+        .fetch_all(&mut *conn)
+        .await
+    {
+        Ok(file_accesses) => Ok(file_accesses),
+        Err(err) => Err(anyhow!("ERROR: Could not fetch file accesses: {err}")),
+    }
+}
+
+pub async fn count_file_accesses(
+    pool: &Pool<Sqlite>,
+    filter: &file_access::FileAccessFilter,
+) -> Result<i64> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let mut builder = QueryBuilder::new("SELECT COUNT(*) FROM file_access WHERE 1 = 1");
+    push_file_access_conditions(filter, &mut builder);
+
+    match builder
+        .build_query_scalar::<i64>()
+        .fetch_one(&mut *conn)
+        .await
+    {
+        Ok(count) => Ok(count),
+        Err(err) => Err(anyhow!("ERROR: Could not count file accesses: {err}")),
+    }
+}
+
+/// Keys of the rules file accesses were recorded for, e.g. for a filter dropdown.
+pub async fn fetch_file_access_keys(pool: &Pool<Sqlite>) -> Result<Vec<String>> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    match query_scalar!("SELECT DISTINCT key FROM file_access_key ORDER BY key")
+        .fetch_all(&mut *conn)
+        .await
+    {
+        Ok(keys) => Ok(keys),
+        Err(err) => Err(anyhow!("ERROR: Could not fetch file access keys: {err}")),
+    }
+}
+
+/// Stores an alert unless the rule already raised one for the same exec.
+pub async fn insert_alert(
+    pool: &Pool<Sqlite>,
+    alert: &rules::NewAlert,
+) -> Result<SqliteQueryResult> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let severity = alert.severity.as_str();
+    let related_ids = (!alert.related_ids.is_empty()).then(|| {
+        alert
+            .related_ids
+            .iter()
+            .map(i64::to_string)
+            .collect::<Vec<_>>()
+            .join(",")
+    });
+
+    match query!(
+        "INSERT OR IGNORE INTO alert (rule_id, title, severity, audit_log_id, timestamp, host, user, command, related_ids) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        alert.rule_id,
+        alert.title,
+        severity,
+        alert.audit_log_id,
+        alert.timestamp,
+        alert.host,
+        alert.user,
+        alert.command,
+        related_ids,
+    )
+    .execute(&mut *conn)
+    .await
+    {
+        Ok(result) => Ok(result),
+        Err(err) => Err(anyhow!("ERROR: Could not create alert: {err}")),
+    }
+}
+
+fn push_alert_conditions(filter: &rules::AlertFilter, builder: &mut QueryBuilder<'_, Sqlite>) {
+    if let Some(from) = filter.from {
+        builder.push(" AND timestamp >= ").push_bind(from);
+    }
+    if let Some(to) = filter.to {
+        builder.push(" AND timestamp < ").push_bind(to);
+    }
+    if let Some(rule) = &filter.rule {
+        builder.push(" AND rule_id = ").push_bind(rule.clone());
+    }
+    if let Some(severity) = filter.severity {
+        builder
+            .push(" AND severity = ")
+            .push_bind(severity.as_str());
+    }
+    if let Some(host) = &filter.host {
+        builder.push(" AND host = ").push_bind(host.clone());
+    }
+    match filter.acknowledged {
+        Some(true) => {
+            builder.push(" AND acknowledged_at IS NOT NULL");
+        }
+        Some(false) => {
+            builder.push(" AND acknowledged_at IS NULL");
+        }
+        None => {}
+    }
+}
+
+/// Alerts matching `filter`, newest first.
+pub async fn fetch_alerts(
+    pool: &Pool<Sqlite>,
+    filter: &rules::AlertFilter,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<rules::Alert>> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let mut builder = QueryBuilder::new(
+        "SELECT id, rule_id, title, severity, audit_log_id, timestamp, host, user, command, related_ids, acknowledged_at, acknowledged_by FROM alert WHERE 1 = 1",
+    );
+    push_alert_conditions(filter, &mut builder);
+    builder
+        .push(" ORDER BY timestamp DESC, id DESC LIMIT ")
+        .push_bind(limit)
+        .push(" OFFSET ")
+        .push_bind(offset);
+
+    match builder
+        .build_query_as::<rules::Alert>()
+        .fetch_all(&mut *conn)
+        .await
+    {
+        Ok(alerts) => Ok(alerts),
+        Err(err) => Err(anyhow!("ERROR: Could not fetch alerts: {err}")),
+    }
+}
+
+pub async fn count_alerts(pool: &Pool<Sqlite>, filter: &rules::AlertFilter) -> Result<i64> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let mut builder = QueryBuilder::new("SELECT COUNT(*) FROM alert WHERE 1 = 1");
+    push_alert_conditions(filter, &mut builder);
+
+    match builder
+        .build_query_scalar::<i64>()
+        .fetch_one(&mut *conn)
+        .await
+    {
+        Ok(count) => Ok(count),
+        Err(err) => Err(anyhow!("ERROR: Could not count alerts: {err}")),
+    }
+}
+
+pub async fn fetch_alert(pool: &Pool<Sqlite>, id: i64) -> Result<Option<rules::Alert>> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    match query_as!(
+        rules::Alert,
+        "SELECT id, rule_id, title, severity, audit_log_id, timestamp, host, user, command, related_ids, acknowledged_at, acknowledged_by FROM alert WHERE id = ?",
+        id
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    {
+        Ok(alert) => Ok(alert),
+        Err(err) => Err(anyhow!("ERROR: Could not fetch alert {id}: {err}")),
+    }
+}
+
+/// Marks alert `id` as acknowledged by `username`, returning whether it was unacknowledged.
+pub async fn acknowledge_alert(pool: &Pool<Sqlite>, id: i64, username: &str) -> Result<bool> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let now = Utc::now();
+    match query!(
+        "UPDATE alert SET acknowledged_at = ?, acknowledged_by = ? WHERE id = ? AND acknowledged_at IS NULL",
+        now,
+        username,
+        id
+    )
+    .execute(&mut *conn)
+    .await
+    {
+        Ok(query_result) => Ok(query_result.rows_affected() > 0),
+        Err(err) => Err(anyhow!("ERROR: Could not acknowledge alert {id}: {err}")),
+    }
+}
+
+/// Adds `observation` seen at `timestamp` to the baseline, returning whether it was new.
+pub async fn record_baseline(
+    pool: &Pool<Sqlite>,
+    observation: &baseline::Observation,
+    timestamp: DateTime<Utc>,
+) -> Result<bool> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let kind = observation.kind.as_str();
+    let inserted = match query!(
+        "INSERT OR IGNORE INTO baseline (kind, host, user, parent, program, first_seen, last_seen, count) VALUES (?, ?, ?, ?, ?, ?, ?, 1)",
+        kind,
+        observation.host,
+        observation.user,
+        observation.parent,
+        observation.program,
+        timestamp,
+        timestamp,
+    )
+    .execute(&mut *conn)
+    .await
+    {
+        Ok(result) => result.rows_affected() > 0,
+        Err(err) => return Err(anyhow!("ERROR: Could not create baseline entry: {err}")),
+    };
+    if inserted {
+        return Ok(true);
+    }
+
+    match query!(
+        "UPDATE baseline SET last_seen = MAX(last_seen, ?), count = count + 1 WHERE kind = ? AND host = ? AND user = ? AND parent = ? AND program = ?",
+        timestamp,
+        kind,
+        observation.host,
+        observation.user,
+        observation.parent,
+        observation.program,
+    )
+    .execute(&mut *conn)
+    .await
+    {
+        Ok(_) => Ok(false),
+        Err(err) => Err(anyhow!("ERROR: Could not update baseline entry: {err}")),
+    }
+}
+
+/// When the baseline of `host` started, `None` before its first exec.
+pub async fn fetch_baseline_start(
+    pool: &Pool<Sqlite>,
+    host: &str,
+) -> Result<Option<DateTime<Utc>>> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    match query_scalar!(
+        "SELECT MIN(first_seen) as \"first_seen: DateTime<Utc>\" FROM baseline WHERE host = ?",
+        host
+    )
+    .fetch_one(&mut *conn)
+    .await
+    {
+        Ok(first_seen) => Ok(first_seen),
+        Err(err) => Err(anyhow!(
+            "ERROR: Could not fetch baseline start of {host:?}: {err}"
+        )),
+    }
+}
+
+fn push_baseline_conditions(
+    filter: &baseline::BaselineFilter,
+    builder: &mut QueryBuilder<'_, Sqlite>,
+) {
+    if let Some(kind) = filter.kind {
+        builder.push(" AND kind = ").push_bind(kind.as_str());
+    }
+    if let Some(host) = &filter.host {
+        builder.push(" AND host = ").push_bind(host.clone());
+    }
+    if let Some(user) = &filter.user {
+        builder.push(" AND user = ").push_bind(user.clone());
+    }
+    if let Some(program) = &filter.program {
+        builder.push(" AND program = ").push_bind(program.clone());
+    }
+}
+
+/// Baseline entries matching `filter`, most recently learned first.
+pub async fn fetch_baseline(
+    pool: &Pool<Sqlite>,
+    filter: &baseline::BaselineFilter,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<baseline::BaselineEntry>> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let mut builder = QueryBuilder::new(
+        "SELECT kind, host, user, parent, program, first_seen, last_seen, count FROM baseline WHERE 1 = 1",
+    );
+    push_baseline_conditions(filter, &mut builder);
+    builder
+        .push(" ORDER BY first_seen DESC LIMIT ")
+        .push_bind(limit)
+        .push(" OFFSET ")
+        .push_bind(offset);
+
+    match builder
+        .build_query_as::<baseline::BaselineEntry>()
+        .fetch_all(&mut *conn)
+        .await
+    {
+        Ok(entries) => Ok(entries),
+        Err(err) => Err(anyhow!("ERROR: Could not fetch baseline: {err}")),
+    }
+}
+
+pub async fn count_baseline(pool: &Pool<Sqlite>, filter: &baseline::BaselineFilter) -> Result<i64> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let mut builder = QueryBuilder::new("SELECT COUNT(*) FROM baseline WHERE 1 = 1");
+    push_baseline_conditions(filter, &mut builder);
+
+    match builder
+        .build_query_scalar::<i64>()
+        .fetch_one(&mut *conn)
+        .await
+    {
+        Ok(count) => Ok(count),
+        Err(err) => Err(anyhow!("ERROR: Could not count baseline: {err}")),
+    }
+}
+
+/// Latest exec of the parent process, from the point of view of audit log `a`.
+const PARENT_EXEC_QUERY: &str = "FROM audit_log p WHERE p.host IS a.host AND p.pid = a.ppid AND (p.timestamp, p.id) < (a.timestamp, a.id) ORDER BY p.timestamp DESC, p.id DESC LIMIT 1";
+
+/// Latest exec of the parent of an exec.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct ExecParent {
+    pub program: String,
+    pub exe: Option<String>,
+    pub command: String,
+}
+
+/// Latest exec of the parent of audit log `id`.
+pub async fn fetch_exec_parent(pool: &Pool<Sqlite>, id: i64) -> Result<Option<ExecParent>> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let mut builder = QueryBuilder::new(format!(
+        "SELECT parent.program, parent.exe, parent.command FROM audit_log a JOIN audit_log parent ON parent.id = (SELECT p.id {PARENT_EXEC_QUERY}) WHERE a.id = "
+    ));
+    builder.push_bind(id);
+
+    match builder
+        .build_query_as::<ExecParent>()
+        .fetch_optional(&mut *conn)
+        .await
+    {
+        Ok(parent) => Ok(parent),
+        Err(err) => Err(anyhow!(
+            "ERROR: Could not fetch parent of audit_log {id}: {err}"
+        )),
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct RuleEventRow {
+    id: i64,
+    timestamp: DateTime<Utc>,
+    program: String,
+    exe: Option<String>,
+    args: String,
+    command: String,
+    user: Option<String>,
+    login_user: Option<String>,
+    host: Option<String>,
+    key: Option<String>,
+    pid: Option<i64>,
+    ppid: Option<i64>,
+    ses: Option<i64>,
+    raw: Option<String>,
+    parent_program: Option<String>,
+    parent_exe: Option<String>,
+    parent_command: Option<String>,
+}
+
+impl From<RuleEventRow> for rules::RuleEvent {
+    fn from(row: RuleEventRow) -> Self {
+        rules::RuleEvent {
+            id: row.id,
+            timestamp: row.timestamp,
+            program: row.program,
+            exe: row.exe,
+            // Stored with a space in front of every argument.
+            args: row
+                .args
+                .strip_prefix(' ')
+                .map(String::from)
+                .unwrap_or(row.args),
+            command: row.command,
+            user: row.user,
+            login_user: row.login_user,
+            cwd: row.raw.as_deref().and_then(parser::cwd_from_raw),
+            host: row.host,
+            key: row.key,
+            pid: row.pid,
+            ppid: row.ppid,
+            ses: row.ses,
+            parent_program: row.parent_program,
+            parent_exe: row.parent_exe,
+            parent_command: row.parent_command,
+        }
+    }
+}
+
+/// Stored execs after row `after_id` in the shape rules look at, optionally with the
+/// latest exec of their parent.
+pub async fn fetch_rule_events(
+    pool: &Pool<Sqlite>,
+    after_id: i64,
+    limit: i64,
+    with_parent: bool,
+) -> Result<Vec<rules::RuleEvent>> {
+    let mut conn = get_connection(pool)
+        .await
+        .ok_or(anyhow!("ERROR: Failed to get connection from db pool"))?;
+
+    let parent_columns = if with_parent {
+        format!(
+            "(SELECT p.program {PARENT_EXEC_QUERY}) AS parent_program, (SELECT p.exe {PARENT_EXEC_QUERY}) AS parent_exe, (SELECT p.command {PARENT_EXEC_QUERY}) AS parent_command"
+        )
+    } else {
+        String::from("NULL AS parent_program, NULL AS parent_exe, NULL AS parent_command")
+    };
+    let mut builder = QueryBuilder::new(format!(
+        "SELECT a.id, a.timestamp, a.program, a.exe, a.args, a.command, COALESCE(a.user, CAST(a.uid AS TEXT)) AS user, COALESCE(a.login_user, CAST(a.auid AS TEXT)) AS login_user, a.host, a.key, a.pid, a.ppid, a.ses, a.raw, {parent_columns} FROM audit_log a WHERE a.id > "
+    ));
+    builder
+        .push_bind(after_id)
+        .push(" ORDER BY a.id LIMIT ")
+        .push_bind(limit);
+
+    match builder
+        .build_query_as::<RuleEventRow>()
+        .fetch_all(&mut *conn)
+        .await
+    {
+        Ok(rows) => Ok(rows.into_iter().map(rules::RuleEvent::from).collect()),
+        Err(err) => Err(anyhow!(
+            "ERROR: Could not fetch audit logs for rules: {err}"
+        )),
+    }
 }
@@ -0,0 +1,128 @@
+use serde::Deserialize;
+
+use crate::parser;
+
+pub const EXPORT_CHUNK_SIZE: i64 = 1000;
+
+const CSV_HEADER: &str =
+    "id,log_type,timestamp,program,args,argc,command,serial,host,pid,ppid,uid,user,ses,tty,auid,login_user,key,success,exit,errno,raw\r\n";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    #[default]
+    Ndjson,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson",
+            ExportFormat::Json => "application/json",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Json => "json",
+        }
+    }
+
+    pub fn header(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => CSV_HEADER,
+            ExportFormat::Ndjson => "",
+            ExportFormat::Json => "[",
+        }
+    }
+
+    pub fn footer(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv | ExportFormat::Ndjson => "",
+            ExportFormat::Json => "]",
+        }
+    }
+
+    /// Encodes one chunk of rows. `first` tells whether any row was written before it.
+    pub fn encode_chunk(&self, audit_logs: &[parser::AuditLogDetail], first: bool) -> String {
+        let mut out = String::new();
+
+        for (i, audit_log) in audit_logs.iter().enumerate() {
+            match self {
+                ExportFormat::Csv => out.push_str(&csv_row(audit_log)),
+                ExportFormat::Ndjson => {
+                    out.push_str(&serde_json::to_string(audit_log).unwrap_or_default());
+                    out.push('\n');
+                }
+                ExportFormat::Json => {
+                    if !(first && i == 0) {
+                        out.push(',');
+                    }
+                    out.push_str(&serde_json::to_string(audit_log).unwrap_or_default());
+                }
+            }
+        }
+
+        out
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    // Spreadsheets evaluate cells starting like a formula, so those are kept as text.
+    let value = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{value}")
+    } else {
+        value.to_string()
+    };
+
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+fn csv_number<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map(T::to_string).unwrap_or_default()
+}
+
+fn csv_optional<T: ToString>(value: &Option<T>) -> String {
+    value
+        .as_ref()
+        .map(|v| csv_field(&v.to_string()))
+        .unwrap_or_default()
+}
+
+fn csv_row(audit_log: &parser::AuditLogDetail) -> String {
+    let fields = [
+        audit_log.id.to_string(),
+        csv_field(&audit_log.log_type),
+        csv_field(&audit_log.timestamp),
+        csv_field(&audit_log.program),
+        csv_field(&audit_log.args),
+        audit_log.argc.to_string(),
+        csv_field(&audit_log.command),
+        csv_number(&audit_log.serial),
+        csv_optional(&audit_log.host),
+        csv_number(&audit_log.pid),
+        csv_number(&audit_log.ppid),
+        csv_number(&audit_log.uid),
+        csv_optional(&audit_log.user),
+        csv_number(&audit_log.ses),
+        csv_optional(&audit_log.tty),
+        csv_number(&audit_log.auid),
+        csv_optional(&audit_log.login_user),
+        csv_optional(&audit_log.key),
+        csv_number(&audit_log.success),
+        csv_number(&audit_log.exit),
+        csv_optional(&audit_log.errno),
+        csv_field(&audit_log.raw.join("\n")),
+    ];
+
+    format!("{}\r\n", fields.join(","))
+}
@@ -0,0 +1,146 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+const ARCH_X86_64: &str = "c000003e";
+
+/// Names of the x86_64 syscalls file watches usually fire for, used when auditd does
+/// not write the ENRICHED format.
+const X86_64_SYSCALLS: &[(u32, &str)] = &[
+    (2, "open"),
+    (4, "stat"),
+    (6, "lstat"),
+    (21, "access"),
+    (59, "execve"),
+    (76, "truncate"),
+    (77, "ftruncate"),
+    (82, "rename"),
+    (83, "mkdir"),
+    (84, "rmdir"),
+    (85, "creat"),
+    (86, "link"),
+    (87, "unlink"),
+    (88, "symlink"),
+    (90, "chmod"),
+    (91, "fchmod"),
+    (92, "chown"),
+    (93, "fchown"),
+    (94, "lchown"),
+    (132, "utime"),
+    (188, "setxattr"),
+    (189, "lsetxattr"),
+    (190, "fsetxattr"),
+    (197, "removexattr"),
+    (198, "lremovexattr"),
+    (199, "fremovexattr"),
+    (235, "utimes"),
+    (257, "openat"),
+    (258, "mkdirat"),
+    (260, "fchownat"),
+    (263, "unlinkat"),
+    (264, "renameat"),
+    (265, "linkat"),
+    (266, "symlinkat"),
+    (268, "fchmodat"),
+    (269, "faccessat"),
+    (280, "utimensat"),
+    (316, "renameat2"),
+    (322, "execveat"),
+    (437, "openat2"),
+    (439, "faccessat2"),
+];
+
+const O_ACCMODE: u64 = 0o3;
+const O_CREAT: u64 = 0o100;
+const O_TRUNC: u64 = 0o1000;
+
+/// Resolves a syscall number to its name, for x86_64 only.
+pub fn syscall_name(arch: Option<&str>, number: &str) -> Option<&'static str> {
+    if arch != Some(ARCH_X86_64) {
+        return None;
+    }
+    let number: u32 = number.parse().ok()?;
+
+    X86_64_SYSCALLS
+        .iter()
+        .find(|(n, _)| *n == number)
+        .map(|(_, name)| *name)
+}
+
+/// Classifies what a syscall did to a path, in the terms of watch permissions.
+///
+/// Returns `read`, `write`, `execute`, `attribute`, `create` or `delete`, or the
+/// syscall name when it fits none of them. `flags` is the flags argument of `open`
+/// style syscalls as logged, in hex.
+pub fn operation(syscall: &str, nametype: Option<&str>, flags: Option<&str>) -> String {
+    match nametype {
+        Some("CREATE") => return String::from("create"),
+        Some("DELETE") => return String::from("delete"),
+        _ => {}
+    }
+
+    let operation = match syscall {
+        "open" | "openat" => match flags.and_then(|flags| u64::from_str_radix(flags, 16).ok()) {
+            Some(flags) if flags & (O_ACCMODE | O_CREAT | O_TRUNC) != 0 => "write",
+            Some(_) => "read",
+            None => "open",
+        },
+        "creat" | "truncate" | "ftruncate" => "write",
+        "execve" | "execveat" => "execute",
+        "chmod" | "fchmod" | "fchmodat" | "chown" | "fchown" | "lchown" | "fchownat"
+        | "setxattr" | "lsetxattr" | "fsetxattr" | "removexattr" | "lremovexattr"
+        | "fremovexattr" | "utime" | "utimes" | "utimensat" => "attribute",
+        "stat" | "lstat" | "access" | "faccessat" | "faccessat2" => "read",
+        _ => syscall,
+    };
+
+    operation.to_string()
+}
+
+/// Stored access to a watched path, one row per path of an event.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct FileAccess {
+    pub id: i64,
+    pub timestamp: String,
+    pub host: Option<String>,
+    pub pid: Option<i64>,
+    pub ppid: Option<i64>,
+    pub uid: Option<i64>,
+    pub user: Option<String>,
+    pub auid: Option<i64>,
+    pub login_user: Option<String>,
+    pub ses: Option<i64>,
+    pub exe: Option<String>,
+    pub syscall: Option<String>,
+    pub path: String,
+    pub nametype: Option<String>,
+    pub operation: String,
+    pub success: Option<bool>,
+    pub exit: Option<i64>,
+    pub key: Option<String>,
+}
+
+/// Field filters for the file access endpoint.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct FileAccessFilter {
+    /// Inclusive lower bound on the timestamp.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<DateTime<Utc>>,
+    /// Exclusive upper bound on the timestamp.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<DateTime<Utc>>,
+    /// Audit rule key, e.g. `shadow` for `-k shadow`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// Exact path, or a directory prefix ending in `/`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub operation: Option<String>,
+    /// User name or numeric uid.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub success: Option<bool>,
+}
@@ -0,0 +1,217 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+
+use crate::{db_util, parser, search};
+
+type Trigram = (char, char, char);
+
+#[derive(Debug)]
+struct IndexedCommand {
+    command: String,
+    /// Row ids of every occurrence of `command`, in ascending order.
+    ids: Vec<i64>,
+}
+
+/// In-memory trigram index over the stored audit logs.
+///
+/// Built once at startup, then kept in sync by the watcher on insert and by the
+/// delete endpoint on removal, so searches never have to scan the database.
+/// Postings point at distinct commands rather than rows, so repeated commands
+/// are only scored once per search.
+#[derive(Debug, Default)]
+pub struct SearchIndex {
+    entries: BTreeMap<i64, parser::AuditLogResponse>,
+    commands: HashMap<u64, IndexedCommand>,
+    command_slots: HashMap<String, u64>,
+    next_slot: u64,
+    /// Command slots containing each trigram, in ascending order.
+    postings: HashMap<Trigram, Vec<u64>>,
+}
+
+fn command_trigrams(command: &str) -> Vec<Trigram> {
+    let mut trigrams = search::get_trigrams(&search::fold_case(command));
+    trigrams.sort_unstable();
+    trigrams.dedup();
+    trigrams
+}
+
+impl SearchIndex {
+    pub async fn build(pool: &sqlx::Pool<sqlx::Sqlite>) -> Result<SearchIndex> {
+        let audit_logs = db_util::fetch_audit_logs(pool)
+            .await
+            .context("ERROR: Could not fetch audit logs to build search index")?;
+
+        let mut index = SearchIndex::default();
+        for audit_log in audit_logs {
+            index.insert(audit_log);
+        }
+
+        println!("INFO: Built search index with {} entries", index.len());
+
+        Ok(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn insert(&mut self, audit_log: parser::AuditLogResponse) {
+        let id = audit_log.id;
+
+        let slot = match self.command_slots.get(&audit_log.command) {
+            Some(&slot) => slot,
+            None => {
+                let slot = self.next_slot;
+                self.next_slot += 1;
+
+                for trigram in command_trigrams(&audit_log.command) {
+                    self.postings.entry(trigram).or_default().push(slot);
+                }
+                self.command_slots.insert(audit_log.command.clone(), slot);
+                self.commands.insert(
+                    slot,
+                    IndexedCommand {
+                        command: audit_log.command.clone(),
+                        ids: Vec::new(),
+                    },
+                );
+
+                slot
+            }
+        };
+
+        if let Some(indexed) = self.commands.get_mut(&slot) {
+            match indexed.ids.binary_search(&id) {
+                Ok(_) => {}
+                Err(position) => indexed.ids.insert(position, id),
+            }
+        }
+
+        self.entries.insert(id, audit_log);
+    }
+
+    /// Removes the entries with the given ids, returning how many were indexed.
+    pub fn remove(&mut self, ids: &[i64]) -> usize {
+        let mut removed = HashSet::new();
+        let mut affected_slots = HashSet::new();
+        for id in ids {
+            if let Some(audit_log) = self.entries.remove(id) {
+                removed.insert(*id);
+                if let Some(&slot) = self.command_slots.get(&audit_log.command) {
+                    affected_slots.insert(slot);
+                }
+            }
+        }
+
+        for slot in affected_slots {
+            let Some(indexed) = self.commands.get_mut(&slot) else {
+                continue;
+            };
+
+            indexed.ids.retain(|id| !removed.contains(id));
+            if !indexed.ids.is_empty() {
+                continue;
+            }
+
+            let Some(indexed) = self.commands.remove(&slot) else {
+                continue;
+            };
+            self.command_slots.remove(&indexed.command);
+            for trigram in command_trigrams(&indexed.command) {
+                if let Some(slots) = self.postings.get_mut(&trigram) {
+                    slots.retain(|s| *s != slot);
+                    if slots.is_empty() {
+                        self.postings.remove(&trigram);
+                    }
+                }
+            }
+        }
+
+        removed.len()
+    }
+
+    /// Fuzzy searches the commands sharing at least one trigram with `query`.
+    ///
+    /// Ranks like [`search::fuzzy_search_best_n`], with ties going to the newest entry.
+    pub fn search<'a>(
+        &'a self,
+        query: &'a str,
+        n: usize,
+        options: &search::SearchOptions,
+    ) -> Vec<search::SearchResult<'a>> {
+        let mut slots: Vec<u64> = if query.trim().is_empty() {
+            self.commands.keys().copied().collect()
+        } else {
+            command_trigrams(query)
+                .iter()
+                .filter_map(|trigram| self.postings.get(trigram))
+                .flatten()
+                .copied()
+                .collect()
+        };
+        slots.sort_unstable();
+        slots.dedup();
+
+        let now = Utc::now();
+        let boost = |score: f32, id: i64| match options.recency_half_life {
+            Some(half_life) => {
+                search::recency_boost(score, &self.entries[&id].timestamp, now, half_life)
+            }
+            None => score,
+        };
+
+        // (score, id, count) of every ranked entry.
+        let mut ranked: Vec<(f32, i64, usize)> = Vec::new();
+        for slot in slots {
+            let indexed = &self.commands[&slot];
+            let ids: Vec<i64> = indexed
+                .ids
+                .iter()
+                .copied()
+                .filter(|id| options.matches_key(&self.entries[id]))
+                .collect();
+            if ids.is_empty() {
+                continue;
+            }
+            let score = search::fuzzy_compare(query, &indexed.command);
+
+            if options.group {
+                if let Some(&id) = ids.last() {
+                    ranked.push((boost(score, id), id, ids.len()));
+                }
+            } else {
+                ranked.extend(ids.iter().map(|&id| (boost(score, id), id, 1)));
+            }
+        }
+        ranked.retain(|(score, _, _)| *score >= options.min_score);
+
+        let compare = |(s1, id1, _): &(f32, i64, usize), (s2, id2, _): &(f32, i64, usize)| {
+            s2.partial_cmp(s1).unwrap().then(id2.cmp(id1))
+        };
+        if n < ranked.len() {
+            ranked.select_nth_unstable_by(n, compare);
+            ranked.truncate(n);
+        }
+        ranked.sort_unstable_by(compare);
+
+        ranked
+            .into_iter()
+            .map(|(score, id, count)| {
+                let log = &self.entries[&id];
+                search::SearchResult {
+                    log,
+                    score,
+                    matches: search::fuzzy_match_ranges(query, &log.command),
+                    count,
+                    last_seen: &log.timestamp,
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,22 @@
+pub mod auth;
+pub mod baseline;
+pub mod chain;
+pub mod db_util;
+pub mod errno;
+pub mod export;
+pub mod file_access;
+pub mod index;
+pub mod listen;
+pub mod login;
+pub mod notifier;
+pub mod parser;
+pub mod redact;
+pub mod rules;
+pub mod search;
+pub mod server;
+pub mod session;
+pub mod sigma;
+pub mod stats;
+pub mod sudo;
+pub mod tree;
+pub mod watcher;
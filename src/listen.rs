@@ -0,0 +1,177 @@
+use std::{
+    fmt,
+    fs::File,
+    io::BufReader,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, Context, Result};
+use rustls::{
+    crypto::ring,
+    pki_types::CertificateDer,
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+    ServerConfig,
+};
+
+pub const TLS_RELOAD_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Address the server listens on, configured as `host:port`, `[v6]:port` or `unix:/path`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl FromStr for ListenAddr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if let Some(path) = s.strip_prefix("unix:") {
+            if path.is_empty() {
+                return Err(anyhow!("ERROR: Missing path in listen address '{s}'"));
+            }
+            return Ok(ListenAddr::Unix(PathBuf::from(path)));
+        }
+
+        s.parse()
+            .map(ListenAddr::Tcp)
+            .map_err(|_| anyhow!("ERROR: Invalid listen address '{s}'"))
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenAddr::Tcp(addr) => write!(f, "{addr}"),
+            ListenAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// Parses a comma separated list of listen addresses.
+pub fn parse_listen_addrs(s: &str) -> Result<Vec<ListenAddr>> {
+    let addrs = s
+        .split(',')
+        .filter(|addr| !addr.trim().is_empty())
+        .map(ListenAddr::from_str)
+        .collect::<Result<Vec<_>>>()?;
+
+    if addrs.is_empty() {
+        return Err(anyhow!("ERROR: No listen address configured"));
+    }
+
+    Ok(addrs)
+}
+
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+fn load_certified_key(cert_path: &Path, key_path: &Path) -> Result<CertifiedKey> {
+    let mut cert_reader = BufReader::new(
+        File::open(cert_path).context(format!("ERROR: Could not open {cert_path:?}"))?,
+    );
+    let certs = rustls_pemfile::certs(&mut cert_reader)
+        .collect::<Result<Vec<CertificateDer<'static>>, _>>()
+        .context(format!(
+            "ERROR: Could not read certificates from {cert_path:?}"
+        ))?;
+    if certs.is_empty() {
+        return Err(anyhow!("ERROR: No certificate found in {cert_path:?}"));
+    }
+
+    let mut key_reader = BufReader::new(
+        File::open(key_path).context(format!("ERROR: Could not open {key_path:?}"))?,
+    );
+    let key = rustls_pemfile::private_key(&mut key_reader)
+        .context(format!(
+            "ERROR: Could not read private key from {key_path:?}"
+        ))?
+        .ok_or(anyhow!("ERROR: No private key found in {key_path:?}"))?;
+
+    let signing_key = ring::sign::any_supported_type(&key)
+        .map_err(|err| anyhow!("ERROR: Unsupported private key in {key_path:?}: {err}"))?;
+
+    Ok(CertifiedKey::new(certs, signing_key))
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Serves the current certificate and swaps it when the files on disk change.
+#[derive(Debug)]
+pub struct ReloadingCertResolver {
+    tls: TlsConfig,
+    certified_key: RwLock<Arc<CertifiedKey>>,
+    modified: RwLock<(Option<SystemTime>, Option<SystemTime>)>,
+}
+
+impl ReloadingCertResolver {
+    pub fn new(tls: TlsConfig) -> Result<ReloadingCertResolver> {
+        let certified_key = load_certified_key(&tls.cert_path, &tls.key_path)?;
+        let modified = (modified(&tls.cert_path), modified(&tls.key_path));
+
+        Ok(ReloadingCertResolver {
+            tls,
+            certified_key: RwLock::new(Arc::new(certified_key)),
+            modified: RwLock::new(modified),
+        })
+    }
+
+    /// Reloads the certificate and key if either file changed since the last load.
+    ///
+    /// A failed reload keeps serving the previous certificate.
+    pub fn reload_if_changed(&self) -> Result<bool> {
+        let current = (modified(&self.tls.cert_path), modified(&self.tls.key_path));
+        if self.modified.read().map(|m| *m == current).unwrap_or(false) {
+            return Ok(false);
+        }
+
+        let certified_key = load_certified_key(&self.tls.cert_path, &self.tls.key_path)?;
+        if let Ok(mut key) = self.certified_key.write() {
+            *key = Arc::new(certified_key);
+        }
+        if let Ok(mut modified) = self.modified.write() {
+            *modified = current;
+        }
+
+        Ok(true)
+    }
+}
+
+impl ResolvesServerCert for ReloadingCertResolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        self.certified_key.read().ok().map(|key| key.clone())
+    }
+}
+
+pub fn server_config(resolver: Arc<ReloadingCertResolver>) -> Result<ServerConfig> {
+    Ok(
+        ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_cert_resolver(resolver),
+    )
+}
+
+/// Periodically checks the certificate files for changes, e.g. after a renewal.
+pub async fn watch_certificates(resolver: Arc<ReloadingCertResolver>) {
+    let mut interval = tokio::time::interval(TLS_RELOAD_INTERVAL);
+    loop {
+        interval.tick().await;
+        match resolver.reload_if_changed() {
+            Ok(true) => println!("INFO: Reloaded TLS certificate"),
+            Ok(false) => {}
+            Err(err) => eprintln!("ERROR: Could not reload TLS certificate: {err}"),
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Stored login or authentication event, see [`crate::parser::AuthEvent`].
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Login {
+    pub id: i64,
+    pub record_type: String,
+    pub timestamp: String,
+    pub host: Option<String>,
+    pub pid: Option<i64>,
+    pub uid: Option<i64>,
+    pub auid: Option<i64>,
+    pub ses: Option<i64>,
+    pub account: Option<String>,
+    pub op: Option<String>,
+    pub exe: Option<String>,
+    pub hostname: Option<String>,
+    pub addr: Option<String>,
+    pub terminal: Option<String>,
+    pub success: bool,
+}
+
+/// Field filters for the logins endpoint.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct LoginFilter {
+    /// Inclusive lower bound on the timestamp.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<DateTime<Utc>>,
+    /// Exclusive upper bound on the timestamp.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<DateTime<Utc>>,
+    /// Record type, e.g. `USER_LOGIN`.
+    #[serde(skip_serializing_if = "Option::is_none", rename = "type")]
+    pub record_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    /// Remote address or host name the login came from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub success: Option<bool>,
+}
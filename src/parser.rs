@@ -9,13 +9,123 @@ use std::{
     path::Path,
 };
 
-use crate::db_util;
+use crate::{db_util, file_access};
 
 pub const LOG_TYPE_EXECVE: &str = "EXECVE";
+pub const LOG_TYPE_SYSCALL: &str = "SYSCALL";
+pub const LOG_TYPE_EOE: &str = "EOE";
+pub const LOG_TYPE_USER_LOGIN: &str = "USER_LOGIN";
+pub const LOG_TYPE_USER_AUTH: &str = "USER_AUTH";
+pub const LOG_TYPE_USER_START: &str = "USER_START";
+pub const LOG_TYPE_USER_END: &str = "USER_END";
+pub const LOG_TYPE_CRED_ACQ: &str = "CRED_ACQ";
+pub const LOG_TYPE_USER_CMD: &str = "USER_CMD";
+pub const LOG_TYPE_PATH: &str = "PATH";
+pub const LOG_TYPE_CWD: &str = "CWD";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordKind {
+    Execve,
+    Syscall,
+    EndOfEvent,
+    /// Authentication and login records written by PAM and login programs.
+    Auth,
+    /// Commands run through sudo.
+    UserCommand,
+    /// A path a syscall operated on, one record per path.
+    Path,
+    Cwd,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RecordType {
+    pub name: &'static str,
+    pub kind: RecordKind,
+    /// Written by user space as an event of its own, without a trailing EOE.
+    pub standalone: bool,
+}
+
+/// Record types auditor understands, every other type is ignored.
+pub const RECORD_TYPES: &[RecordType] = &[
+    RecordType {
+        name: LOG_TYPE_EXECVE,
+        kind: RecordKind::Execve,
+        standalone: false,
+    },
+    RecordType {
+        name: LOG_TYPE_SYSCALL,
+        kind: RecordKind::Syscall,
+        standalone: false,
+    },
+    RecordType {
+        name: LOG_TYPE_EOE,
+        kind: RecordKind::EndOfEvent,
+        standalone: false,
+    },
+    RecordType {
+        name: LOG_TYPE_PATH,
+        kind: RecordKind::Path,
+        standalone: false,
+    },
+    RecordType {
+        name: LOG_TYPE_CWD,
+        kind: RecordKind::Cwd,
+        standalone: false,
+    },
+    RecordType {
+        name: LOG_TYPE_USER_LOGIN,
+        kind: RecordKind::Auth,
+        standalone: true,
+    },
+    RecordType {
+        name: LOG_TYPE_USER_AUTH,
+        kind: RecordKind::Auth,
+        standalone: true,
+    },
+    RecordType {
+        name: LOG_TYPE_USER_START,
+        kind: RecordKind::Auth,
+        standalone: true,
+    },
+    RecordType {
+        name: LOG_TYPE_USER_END,
+        kind: RecordKind::Auth,
+        standalone: true,
+    },
+    RecordType {
+        name: LOG_TYPE_CRED_ACQ,
+        kind: RecordKind::Auth,
+        standalone: true,
+    },
+    RecordType {
+        name: LOG_TYPE_USER_CMD,
+        kind: RecordKind::UserCommand,
+        standalone: true,
+    },
+];
+
+pub fn record_type(name: &str) -> Option<&'static RecordType> {
+    RECORD_TYPES
+        .iter()
+        .find(|record_type| record_type.name == name)
+}
+
+/// auditd joins the keys of a rule with `\x01`, hex encoding the whole field.
+const KEY_SEPARATOR: char = '\x01';
+
+/// `(uint32)-1`, which the kernel reports for an unset auid or session id.
+const UNSET_ID: &str = "4294967295";
+
+fn parse_id(value: &str) -> Option<i64> {
+    if value == UNSET_ID {
+        return None;
+    }
+    value.parse().ok()
+}
 
 fn line_regex() -> &'static regex::Regex {
     static LINE_REGEX: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
-    LINE_REGEX.get_or_init(|| regex::Regex::new(r#"(\w+)=("(?:\\.|[^"\\])*"|[^\s]+)"#).unwrap())
+    LINE_REGEX.get_or_init(|| regex::Regex::new(r#"(\w+)=("(?:\\.|[^"\\])*"|[^\s\x1d]+)"#).unwrap())
 }
 
 fn timestamp_regex() -> &'static regex::Regex {
@@ -23,10 +133,513 @@ fn timestamp_regex() -> &'static regex::Regex {
     TIMESTAMP_REGEX.get_or_init(|| regex::Regex::new(r#"audit\(([^:]+):"#).unwrap())
 }
 
-#[derive(Debug, Serialize)]
+fn serial_regex() -> &'static regex::Regex {
+    static SERIAL_REGEX: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
+    SERIAL_REGEX.get_or_init(|| regex::Regex::new(r#"audit\([^:]+:(\d+)\)"#).unwrap())
+}
+
+/// A single line of the audit log.
+///
+/// Every record of one audit event shares the `msg=audit(<timestamp>:<serial>)` stamp.
+#[derive(Debug, Default, Serialize, Clone)]
+pub struct AuditRecord {
+    pub node: Option<String>,
+    pub record_type: String,
+    pub timestamp: DateTime<Utc>,
+    pub serial: u64,
+    /// Key/value pairs following the stamp, with values as they appear in the line.
+    pub fields: Vec<(String, String)>,
+    pub raw: String,
+}
+
+impl AuditRecord {
+    pub fn parse(line: &str) -> Result<AuditRecord> {
+        let mut parts = Vec::new();
+
+        // User space records nest their own fields in `msg='...'`, which are flattened
+        // into the outer fields.
+        let sections = match line.find(" msg='") {
+            Some(start) => {
+                let inner_start = start + " msg='".len();
+                let inner_end = line[inner_start..]
+                    .find('\'')
+                    .map_or(line.len(), |len| inner_start + len);
+                [
+                    &line[..start],
+                    &line[inner_start..inner_end],
+                    line.get(inner_end + 1..).unwrap_or_default(),
+                ]
+            }
+            None => [line, "", ""],
+        };
+        for section in sections {
+            for captures in line_regex().captures_iter(section) {
+                parts.push((captures[1].to_string(), captures[2].to_string()));
+            }
+        }
+
+        let mut parts = parts.into_iter().peekable();
+
+        let node = match parts.peek() {
+            Some((key, _)) if key == "node" => parts.next().map(|(_, value)| value),
+            _ => None,
+        };
+
+        let record_type = parts
+            .next()
+            .context(format!("ERROR: Missing log type in line: {}", line))?
+            .1;
+
+        let timestamp_str = parts
+            .next()
+            .context(format!("ERROR: Missing timestamp in line: {}", line))?
+            .1;
+        let timestamp = AuditLog::parse_timestamp(&timestamp_str)
+            .context(format!("ERROR: Invalid timestamp: {}", timestamp_str))?;
+        let serial = serial_regex()
+            .captures(&timestamp_str)
+            .and_then(|captures| captures[1].parse().ok())
+            .unwrap_or_default();
+
+        Ok(AuditRecord {
+            node,
+            record_type,
+            timestamp,
+            serial,
+            fields: parts.collect(),
+            raw: line.to_string(),
+        })
+    }
+
+    /// Returns the value of `key` with surrounding quotes removed.
+    pub fn field(&self, key: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| {
+                value
+                    .strip_prefix('"')
+                    .and_then(|v| v.strip_suffix('"'))
+                    .unwrap_or(value)
+            })
+    }
+
+    /// Like [`AuditRecord::field`], but also decodes values auditd logged as hex.
+    ///
+    /// Only meant for untrusted strings such as `acct` or `cmd`, which are hex encoded
+    /// when they contain spaces, quotes or control characters.
+    pub fn field_decoded(&self, key: &str) -> Option<String> {
+        let raw = self
+            .fields
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())?;
+
+        if let Some(quoted) = raw.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+            return Some(quoted.to_string());
+        }
+
+        Some(decode_hex(raw).unwrap_or_else(|| raw.to_string()))
+    }
+
+    /// Name of the syscall of a `SYSCALL` record.
+    pub fn syscall_name(&self) -> Option<String> {
+        // Only present when auditd writes logs in the ENRICHED format.
+        self.field("SYSCALL").map(String::from).or_else(|| {
+            self.field("syscall")
+                .and_then(|number| file_access::syscall_name(self.field("arch"), number))
+                .map(String::from)
+        })
+    }
+
+    /// Key of the audit rule that fired, with multiple keys separated by commas.
+    pub fn key(&self) -> Option<String> {
+        self.field_decoded("key")
+            .filter(|key| key != "(null)")
+            .map(|key| key.replace(KEY_SEPARATOR, ","))
+    }
+
+    pub fn is_same_event(&self, other: &AuditRecord) -> bool {
+        self.serial == other.serial && self.timestamp == other.timestamp && self.node == other.node
+    }
+}
+
+fn decode_hex(value: &str) -> Option<String> {
+    if value.is_empty() || !value.len().is_multiple_of(2) {
+        return None;
+    }
+
+    let bytes = (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Groups consecutive records into audit events.
+///
+/// An event is complete once its `EOE` record arrives, or once a record of another
+/// event shows up for events that are not terminated by `EOE`. Standalone record
+/// types form an event of their own right away.
+#[derive(Debug, Default)]
+pub struct EventAssembler {
+    pending: Vec<AuditRecord>,
+}
+
+impl EventAssembler {
+    pub fn push(&mut self, record: AuditRecord) -> Vec<Vec<AuditRecord>> {
+        let mut events = Vec::new();
+
+        if self
+            .pending
+            .first()
+            .is_some_and(|first| !first.is_same_event(&record))
+        {
+            events.push(std::mem::take(&mut self.pending));
+        }
+
+        if record_type(&record.record_type).is_some_and(|record_type| record_type.standalone) {
+            events.push(vec![record]);
+        } else if record.record_type == LOG_TYPE_EOE {
+            if !self.pending.is_empty() {
+                events.push(std::mem::take(&mut self.pending));
+            }
+        } else {
+            self.pending.push(record);
+        }
+
+        events
+    }
+
+    pub fn flush(&mut self) -> Option<Vec<AuditRecord>> {
+        if self.pending.is_empty() {
+            None
+        } else {
+            Some(std::mem::take(&mut self.pending))
+        }
+    }
+}
+
+/// Login or authentication event built from a single user space record.
+#[derive(Debug, Default, Serialize, Clone)]
+pub struct AuthEvent {
+    pub record_type: String,
+    pub timestamp: DateTime<Utc>,
+    pub serial: u64,
+    pub host: Option<String>,
+    pub pid: Option<i64>,
+    pub uid: Option<i64>,
+    pub auid: Option<i64>,
+    pub ses: Option<i64>,
+    /// Account the event is about, which for failed logins may not exist.
+    pub account: Option<String>,
+    /// PAM operation, e.g. `PAM:authentication` or `login`.
+    pub op: Option<String>,
+    pub exe: Option<String>,
+    pub hostname: Option<String>,
+    pub addr: Option<String>,
+    pub terminal: Option<String>,
+    pub success: bool,
+    pub raw: Vec<String>,
+}
+
+/// auditd logs `?` for values it does not know.
+fn known(value: Option<String>) -> Option<String> {
+    value.filter(|value| value != "?" && !value.is_empty())
+}
+
+impl AuthEvent {
+    pub fn from_record(record: &AuditRecord) -> AuthEvent {
+        AuthEvent {
+            record_type: record.record_type.clone(),
+            timestamp: record.timestamp,
+            serial: record.serial,
+            host: record.node.clone(),
+            pid: record.field("pid").and_then(|pid| pid.parse().ok()),
+            uid: record.field("uid").and_then(parse_id),
+            auid: record.field("auid").and_then(parse_id),
+            ses: record.field("ses").and_then(parse_id),
+            account: known(record.field_decoded("acct"))
+                .or_else(|| known(record.field("ID").map(String::from)))
+                .or_else(|| known(record.field("id").map(String::from))),
+            op: known(record.field("op").map(String::from)),
+            exe: known(record.field_decoded("exe")),
+            hostname: known(record.field("hostname").map(String::from)),
+            addr: known(record.field("addr").map(String::from)),
+            terminal: known(record.field("terminal").map(String::from)),
+            success: record.field("res") == Some("success"),
+            raw: vec![record.raw.clone()],
+        }
+    }
+}
+
+/// Command a user ran through sudo, from a `USER_CMD` record.
+#[derive(Debug, Default, Serialize, Clone)]
+pub struct UserCommand {
+    pub timestamp: DateTime<Utc>,
+    pub serial: u64,
+    pub host: Option<String>,
+    /// Pid of sudo itself, the command runs in a child process.
+    pub pid: Option<i64>,
+    pub uid: Option<i64>,
+    pub auid: Option<i64>,
+    pub ses: Option<i64>,
+    /// Name of the real user, from the enriched `AUID` field.
+    pub login_user: Option<String>,
+    pub cwd: Option<String>,
+    pub command: String,
+    /// First word of the command, usually the absolute path sudo resolved.
+    pub program: String,
+    pub terminal: Option<String>,
+    pub success: bool,
+    pub raw: Vec<String>,
+}
+
+impl UserCommand {
+    pub fn from_record(record: &AuditRecord) -> Result<UserCommand> {
+        let command = record
+            .field_decoded("cmd")
+            .ok_or(anyhow!("ERROR: Missing cmd in {}", record.raw))?;
+
+        Ok(UserCommand {
+            timestamp: record.timestamp,
+            serial: record.serial,
+            host: record.node.clone(),
+            pid: record.field("pid").and_then(|pid| pid.parse().ok()),
+            uid: record.field("uid").and_then(parse_id),
+            auid: record.field("auid").and_then(parse_id),
+            ses: record.field("ses").and_then(parse_id),
+            login_user: record
+                .field("AUID")
+                .filter(|user| *user != "unset")
+                .map(String::from),
+            cwd: known(record.field_decoded("cwd")),
+            program: command
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            command,
+            terminal: known(record.field("terminal").map(String::from)),
+            success: record.field("res") == Some("success"),
+            raw: vec![record.raw.clone()],
+        })
+    }
+}
+
+fn event_cwd(records: &[AuditRecord]) -> Option<String> {
+    records
+        .iter()
+        .find(|record| record.record_type == LOG_TYPE_CWD)
+        .and_then(|record| known(record.field_decoded("cwd")))
+}
+
+/// Working directory from the raw lines of a stored event.
+pub fn cwd_from_raw(raw: &str) -> Option<String> {
+    let records: Vec<AuditRecord> = raw
+        .lines()
+        .filter(|line| line.contains("type=CWD "))
+        .filter_map(|line| AuditRecord::parse(line).ok())
+        .collect();
+
+    event_cwd(&records)
+}
+
+/// Makes a path logged relative to the working directory of the process absolute.
+fn resolve_path(name: String, cwd: Option<&str>) -> String {
+    match cwd {
+        Some(cwd) if !name.starts_with('/') => format!(
+            "{}/{}",
+            cwd.trim_end_matches('/'),
+            name.strip_prefix("./").unwrap_or(&name)
+        ),
+        _ => name,
+    }
+}
+
+/// A path of a [`FileEvent`], from its `PATH` record.
+#[derive(Debug, Default, Serialize, Clone)]
+pub struct FileEventPath {
+    pub item: i64,
+    pub path: String,
+    /// `NORMAL`, `CREATE`, `DELETE` or `PARENT`.
+    pub nametype: Option<String>,
+    pub operation: String,
+}
+
+/// A syscall on watched files, e.g. from `-w /etc/shadow -p rwa -k shadow`.
+#[derive(Debug, Default, Serialize, Clone)]
+pub struct FileEvent {
+    pub timestamp: DateTime<Utc>,
+    pub serial: u64,
+    pub host: Option<String>,
+    pub pid: Option<i64>,
+    pub ppid: Option<i64>,
+    pub uid: Option<i64>,
+    pub user: Option<String>,
+    pub auid: Option<i64>,
+    pub login_user: Option<String>,
+    pub ses: Option<i64>,
+    pub exe: Option<String>,
+    pub syscall: Option<String>,
+    pub success: Option<bool>,
+    pub exit: Option<i64>,
+    pub key: Option<String>,
+    pub paths: Vec<FileEventPath>,
+    pub raw: Vec<String>,
+}
+
+impl FileEvent {
+    /// Builds a file event from a `SYSCALL` with `PATH` records, skipping executions.
+    pub fn from_event(records: &[AuditRecord]) -> Option<FileEvent> {
+        let syscall = records
+            .iter()
+            .find(|record| record.record_type == LOG_TYPE_SYSCALL)?;
+        let path_records: Vec<_> = records
+            .iter()
+            .filter(|record| record.record_type == LOG_TYPE_PATH)
+            .collect();
+        if path_records.is_empty() {
+            return None;
+        }
+
+        // Only present when auditd writes logs in the ENRICHED format.
+        let syscall_name = syscall.syscall_name();
+        if matches!(syscall_name.as_deref(), Some("execve" | "execveat")) {
+            return None;
+        }
+
+        let cwd = event_cwd(records);
+        let flags = match syscall_name.as_deref() {
+            Some("open") => syscall.field("a1"),
+            Some("openat") => syscall.field("a2"),
+            _ => None,
+        };
+
+        // Parent directories only matter when nothing else was recorded.
+        let named = path_records
+            .iter()
+            .any(|record| record.field("nametype") != Some("PARENT"));
+        let paths = path_records
+            .iter()
+            .filter(|record| !named || record.field("nametype") != Some("PARENT"))
+            .filter_map(|record| {
+                let name = record
+                    .field_decoded("name")
+                    .filter(|name| name != "(null)")?;
+                let path = resolve_path(name, cwd.as_deref());
+                let nametype = record.field("nametype").map(String::from);
+
+                Some(FileEventPath {
+                    item: record.field("item")?.parse().ok()?,
+                    operation: file_access::operation(
+                        syscall_name.as_deref().unwrap_or("unknown"),
+                        nametype.as_deref(),
+                        flags,
+                    ),
+                    path,
+                    nametype,
+                })
+            })
+            .collect();
+
+        Some(FileEvent {
+            timestamp: syscall.timestamp,
+            serial: syscall.serial,
+            host: syscall.node.clone(),
+            pid: syscall.field("pid").and_then(|pid| pid.parse().ok()),
+            ppid: syscall.field("ppid").and_then(|ppid| ppid.parse().ok()),
+            uid: syscall.field("uid").and_then(|uid| uid.parse().ok()),
+            user: syscall.field("UID").map(String::from),
+            auid: syscall.field("auid").and_then(parse_id),
+            login_user: syscall
+                .field("AUID")
+                .filter(|auid| *auid != "unset")
+                .map(String::from),
+            ses: syscall.field("ses").and_then(parse_id),
+            exe: known(syscall.field_decoded("exe")),
+            syscall: syscall_name,
+            success: syscall.field("success").map(|success| success == "yes"),
+            exit: syscall.field("exit").and_then(|exit| exit.parse().ok()),
+            key: syscall.key(),
+            paths,
+            raw: records.iter().map(|record| record.raw.clone()).collect(),
+        })
+    }
+}
+
+/// An assembled event auditor stores.
+#[derive(Debug, Clone)]
+pub enum ParsedEvent {
+    Exec(AuditLog),
+    Auth(AuthEvent),
+    UserCommand(UserCommand),
+    File(FileEvent),
+}
+
+/// Builds the stored form of an event, dispatching on the type of its first record.
+///
+/// Kernel events are executions when they have an `EXECVE` record or are a failed exec,
+/// and file accesses when they only have `PATH` records.
+pub fn parse_event(records: &[AuditRecord]) -> Result<Option<ParsedEvent>> {
+    let Some(first) = records.first() else {
+        return Ok(None);
+    };
+
+    match record_type(&first.record_type).map(|record_type| record_type.kind) {
+        Some(RecordKind::Auth) => Ok(Some(ParsedEvent::Auth(AuthEvent::from_record(first)))),
+        Some(RecordKind::UserCommand) => Ok(Some(ParsedEvent::UserCommand(
+            UserCommand::from_record(first)?,
+        ))),
+        _ => match AuditLog::from_event(records)? {
+            Some(audit_log) => Ok(Some(ParsedEvent::Exec(audit_log))),
+            None => Ok(FileEvent::from_event(records).map(ParsedEvent::File)),
+        },
+    }
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
 pub struct AuditLogResponse {
+    pub id: i64,
     pub timestamp: String,
     pub command: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub success: Option<bool>,
+}
+
+/// Every stored field of an audit log along with the raw records of its event.
+#[derive(Debug, Serialize)]
+pub struct AuditLogDetail {
+    pub id: i64,
+    pub log_type: String,
+    pub timestamp: String,
+    pub program: String,
+    pub args: String,
+    pub argc: i64,
+    pub command: String,
+    pub serial: Option<i64>,
+    pub host: Option<String>,
+    pub exe: Option<String>,
+    pub pid: Option<i64>,
+    pub ppid: Option<i64>,
+    pub uid: Option<i64>,
+    pub user: Option<String>,
+    pub ses: Option<i64>,
+    pub tty: Option<String>,
+    pub auid: Option<i64>,
+    pub login_user: Option<String>,
+    pub key: Option<String>,
+    pub success: Option<bool>,
+    pub exit: Option<i64>,
+    /// Name of the errno a failed syscall returned, e.g. `EACCES`.
+    pub errno: Option<&'static str>,
+    pub raw: Vec<String>,
+    /// The sudo invocation this exec ran for, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sudo: Option<crate::sudo::SudoCommand>,
 }
 
 #[derive(Debug, Default, Serialize, Clone)]
@@ -37,6 +650,34 @@ pub struct AuditLog {
     pub args: Vec<String>,
     pub argc: u32,
     pub command: String,
+    pub serial: u64,
+    pub host: Option<String>,
+    /// Path of the executable the kernel ran, unlike `program` which is whatever argv[0]
+    /// the caller passed.
+    pub exe: Option<String>,
+    pub pid: Option<i64>,
+    pub ppid: Option<i64>,
+    pub uid: Option<i64>,
+    pub user: Option<String>,
+    /// Login session id, `None` for processes not started from a login.
+    pub ses: Option<i64>,
+    pub tty: Option<String>,
+    /// Login uid, which stays the same across `su` and `sudo`.
+    pub auid: Option<i64>,
+    pub login_user: Option<String>,
+    /// Key of the audit rule that logged the event.
+    pub key: Option<String>,
+    /// Whether the syscall succeeded, an exec only fails before the new program runs.
+    pub success: Option<bool>,
+    /// Return value of the syscall, a negated errno when it failed.
+    pub exit: Option<i64>,
+    /// Working directory from the `CWD` record, only kept in memory for detection rules.
+    pub cwd: Option<String>,
+    /// Raw lines of every record in the event.
+    pub raw: Vec<String>,
+    /// Command, arguments and raw lines before redaction, encrypted.
+    #[serde(skip)]
+    pub original: Option<Vec<u8>>,
 }
 
 impl Display for AuditLog {
@@ -51,12 +692,26 @@ impl Display for AuditLog {
 }
 
 impl AuditLog {
+    /// Builds the response for this log as stored under row `id`.
+    pub fn to_response(&self, id: i64) -> AuditLogResponse {
+        AuditLogResponse {
+            id,
+            // Matches the encoding sqlx uses for `DateTime<Utc>` in SQLite.
+            timestamp: self
+                .timestamp
+                .to_rfc3339_opts(chrono::SecondsFormat::AutoSi, false),
+            command: self.command.clone(),
+            key: self.key.clone(),
+            success: self.success,
+        }
+    }
+
     pub fn get_args_string(&self) -> String {
         let args_string = String::new();
 
         self.args
             .iter()
-            .fold(args_string, |acc, arg| format!("{acc} {arg})"))
+            .fold(args_string, |acc, arg| format!("{acc} {arg}"))
     }
 
     fn parse_timestamp(log: &str) -> Result<DateTime<Utc>> {
@@ -84,34 +739,101 @@ impl AuditLog {
     }
 
     pub fn parse_line(line: &str) -> Result<AuditLog> {
-        let mut parts = Vec::new();
+        let record = AuditRecord::parse(line)?;
+        if record.record_type != LOG_TYPE_EXECVE {
+            return Err(anyhow!(""));
+        }
+
+        Self::from_execve(&record)
+    }
+
+    /// Builds an audit log from the records of one event, if it contains an EXECVE record.
+    ///
+    /// The process and user context is taken from the SYSCALL record of the same event.
+    pub fn from_event(records: &[AuditRecord]) -> Result<Option<AuditLog>> {
+        let syscall = records
+            .iter()
+            .find(|record| record.record_type == LOG_TYPE_SYSCALL);
+
+        let mut audit_log = match records
+            .iter()
+            .find(|record| record.record_type == LOG_TYPE_EXECVE)
+        {
+            Some(execve) => Self::from_execve(execve)?,
+            None => match syscall.and_then(|syscall| Self::from_failed_exec(syscall, records)) {
+                Some(audit_log) => audit_log,
+                None => return Ok(None),
+            },
+        };
+        audit_log.raw = records.iter().map(|record| record.raw.clone()).collect();
+        audit_log.cwd = event_cwd(records);
 
-        for captures in line_regex().captures_iter(line) {
-            parts.push((captures[1].to_string(), captures[2].to_string()));
+        if let Some(syscall) = syscall {
+            audit_log.pid = syscall.field("pid").and_then(|pid| pid.parse().ok());
+            audit_log.ppid = syscall.field("ppid").and_then(|ppid| ppid.parse().ok());
+            audit_log.uid = syscall.field("uid").and_then(|uid| uid.parse().ok());
+            audit_log.ses = syscall.field("ses").and_then(parse_id);
+            audit_log.tty = syscall
+                .field("tty")
+                .filter(|tty| *tty != "(none)")
+                .map(String::from);
+            audit_log.auid = syscall.field("auid").and_then(parse_id);
+            // Only present when auditd writes logs in the ENRICHED format.
+            audit_log.user = syscall.field("UID").map(String::from);
+            audit_log.login_user = syscall
+                .field("AUID")
+                .filter(|auid| *auid != "unset")
+                .map(String::from);
+            audit_log.key = syscall.key();
+            audit_log.success = syscall.field("success").map(|success| success == "yes");
+            audit_log.exit = syscall.field("exit").and_then(|exit| exit.parse().ok());
+            // A failed exec logs the executable of the caller.
+            if audit_log.success != Some(false) {
+                audit_log.exe = known(syscall.field_decoded("exe"));
+            }
         }
 
-        let mut parts = parts.iter();
+        Ok(Some(audit_log))
+    }
 
-        let log_type = parts
-            .next()
-            .context(format!("ERROR: Missing log type in line: {}", line))?
-            .1
-            .to_string();
-        if log_type != LOG_TYPE_EXECVE {
-            return Err(anyhow!(""));
+    /// Builds the log of an exec that failed before the kernel logged its arguments, from
+    /// the path it tried to run.
+    fn from_failed_exec(syscall: &AuditRecord, records: &[AuditRecord]) -> Option<AuditLog> {
+        if syscall.field("success") != Some("no")
+            || !matches!(
+                syscall.syscall_name().as_deref(),
+                Some("execve" | "execveat")
+            )
+        {
+            return None;
         }
 
-        let timestamp_str = &parts
-            .next()
-            .context(format!("ERROR: Missing timestamp in line: {}", line))?
-            .1;
-        let timestamp = Self::parse_timestamp(timestamp_str)
-            .context(format!("ERROR: Invalid timestamp: {}", timestamp_str))?;
+        let name = records
+            .iter()
+            .find(|record| record.record_type == LOG_TYPE_PATH && record.field("item") == Some("0"))
+            .and_then(|record| record.field_decoded("name"))
+            .filter(|name| name != "(null)")?;
+        let program = resolve_path(name, event_cwd(records).as_deref());
+
+        Some(AuditLog {
+            log_type: syscall.record_type.clone(),
+            timestamp: syscall.timestamp,
+            command: program.clone(),
+            exe: Some(program.clone()),
+            program,
+            serial: syscall.serial,
+            host: syscall.node.clone(),
+            ..Default::default()
+        })
+    }
+
+    fn from_execve(record: &AuditRecord) -> Result<AuditLog> {
+        let line = &record.raw;
 
         let mut program = String::new();
         let mut args = Vec::new();
         let mut argc = 0;
-        for (key, value) in parts {
+        for (key, value) in &record.fields {
             match key.as_str() {
                 "a0" => {
                     program = value
@@ -146,12 +868,16 @@ impl AuditLog {
         let command = format!("{program} {args}", program = program, args = args.join(" "));
 
         Ok(AuditLog {
-            log_type,
-            timestamp,
+            log_type: record.record_type.clone(),
+            timestamp: record.timestamp,
             program,
             args,
             argc,
             command,
+            serial: record.serial,
+            host: record.node.clone(),
+            raw: vec![record.raw.clone()],
+            ..Default::default()
         })
     }
 
@@ -186,9 +912,19 @@ impl AuditLog {
         let mut string = String::new();
         file.read_to_string(&mut string)?;
 
+        let mut assembler = EventAssembler::default();
+        let mut events = Vec::new();
         for line in string.lines() {
-            match Self::parse_line(line) {
-                Ok(audit_log) => {
+            match AuditRecord::parse(line) {
+                Ok(record) => events.extend(assembler.push(record)),
+                Err(e) => eprintln!("ERROR: {}", e),
+            }
+        }
+        events.extend(assembler.flush());
+
+        for event in events {
+            match Self::from_event(&event) {
+                Ok(Some(audit_log)) => {
                     if audit_logs.is_empty()
                         || audit_log.timestamp > audit_logs.iter().last().unwrap().timestamp
                     {
@@ -196,12 +932,8 @@ impl AuditLog {
                         audit_logs.push_back(audit_log);
                     }
                 }
-                Err(e) => {
-                    let error_message = e.to_string();
-                    if !error_message.is_empty() {
-                        eprintln!("ERROR: {}", error_message);
-                    }
-                }
+                Ok(None) => {}
+                Err(e) => eprintln!("ERROR: {}", e),
             }
         }
 
@@ -0,0 +1,448 @@
+use std::{env, fs, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use chrono::SecondsFormat;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::parser::{self, AuditRecord};
+
+/// Replaces every secret found in a command line.
+pub const REDACTED: &str = "[REDACTED]";
+
+const NONCE_LEN: usize = 12;
+
+/// Patterns applied unless `builtin: false`, as `(name, program, regex, next_arg)`.
+const BUILTIN_PATTERNS: &[(&str, Option<&str>, &str, bool)] = &[
+    (
+        "mysql-password",
+        Some(r"(^|/)(mysql|mysqldump|mysqladmin|mariadb|mariadb-dump)$"),
+        r"^(?:-p|--password=)(?P<secret>.+)$",
+        false,
+    ),
+    (
+        "sshpass",
+        Some(r"(^|/)sshpass$"),
+        r"^-p(?P<secret>.+)$",
+        false,
+    ),
+    ("sshpass", Some(r"(^|/)sshpass$"), r"^-p$", true),
+    ("curl-user", Some(r"(^|/)curl$"), r"^(?:-u|--user)$", true),
+    (
+        "password-option",
+        None,
+        r"(?i)^--?[a-z0-9-]*(?:password|passwd|secret|token|api-?key)[a-z0-9-]*=(?P<secret>.+)$",
+        false,
+    ),
+    (
+        "password-option",
+        None,
+        r"(?i)^--(?:password|passwd|pass|secret|token|api-?key|client-secret|access-token|auth-token)$",
+        true,
+    ),
+    (
+        "authorization-header",
+        None,
+        r"(?i)\b(?:proxy-)?authorization:\s*(?:(?:bearer|basic|token|digest)\s+)?(?P<secret>\S+)",
+        false,
+    ),
+    (
+        "secret-variable",
+        None,
+        r"\b[A-Za-z0-9_]*(?i:secret|token|password|passwd|api_?key|access_?key)[A-Za-z0-9_]*=(?P<secret>[^\s'\x22]+)",
+        false,
+    ),
+    (
+        "url-credentials",
+        None,
+        r"(?i)\b[a-z][a-z0-9+.-]*://[^/\s:@]+:(?P<secret>[^/\s@]+)@",
+        false,
+    ),
+];
+
+fn deserialize_regex<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Regex, D::Error> {
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern).map_err(serde::de::Error::custom)
+}
+
+fn deserialize_optional_regex<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Regex>, D::Error> {
+    deserialize_regex(deserializer).map(Some)
+}
+
+fn default_builtin() -> bool {
+    true
+}
+
+/// A secret to look for in each argument of a command line.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RedactPattern {
+    pub name: String,
+    /// Only applies to programs matching this.
+    #[serde(default, deserialize_with = "deserialize_optional_regex")]
+    pub program: Option<Regex>,
+    /// Redacts its `secret` group, or the whole match without one.
+    #[serde(deserialize_with = "deserialize_regex")]
+    pub regex: Regex,
+    /// Redacts the whole argument following a match instead, e.g. for `--password <secret>`.
+    #[serde(default)]
+    pub next_arg: bool,
+}
+
+impl RedactPattern {
+    fn applies_to(&self, program: &str) -> bool {
+        self.program
+            .as_ref()
+            .is_none_or(|regex| regex.is_match(program))
+    }
+
+    fn redact(&self, arg: &str) -> Option<String> {
+        let mut redacted = String::new();
+        let mut last = 0;
+        for captures in self.regex.captures_iter(arg) {
+            let Some(secret) = captures.name("secret").or(captures.get(0)) else {
+                continue;
+            };
+            if secret.is_empty() {
+                continue;
+            }
+            redacted.push_str(&arg[last..secret.start()]);
+            redacted.push_str(REDACTED);
+            last = secret.end();
+        }
+        redacted.push_str(&arg[last..]);
+        (redacted != arg).then_some(redacted)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RedactConfig {
+    #[serde(default = "default_builtin")]
+    pub builtin: bool,
+    #[serde(default)]
+    pub patterns: Vec<RedactPattern>,
+}
+
+impl Default for RedactConfig {
+    fn default() -> Self {
+        RedactConfig {
+            builtin: true,
+            patterns: Vec::new(),
+        }
+    }
+}
+
+pub fn load_redact_config(path: &Path) -> Result<RedactConfig> {
+    let yaml = fs::read_to_string(path)
+        .context(format!("ERROR: Could not read redaction file {path:?}"))?;
+    serde_yaml::from_str(&yaml).context(format!("ERROR: In redaction file {path:?}"))
+}
+
+/// Key from `REDACT_KEY`, 64 hex digits, that originals are encrypted with.
+pub fn load_key() -> Result<Option<Key>> {
+    let Ok(hex) = env::var("REDACT_KEY") else {
+        return Ok(None);
+    };
+
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()
+        .filter(|bytes| bytes.len() == 32)
+        .ok_or(anyhow!("ERROR: REDACT_KEY must be 64 hex digits"))?;
+    Ok(Some(*Key::from_slice(&bytes)))
+}
+
+/// An exec as it was before redaction, only readable by admins.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Original {
+    pub command: String,
+    pub args: Vec<String>,
+    pub raw: Vec<String>,
+}
+
+/// The stored exec an original belongs to, authenticated along with it so the ciphertext
+/// cannot be moved to another row.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OriginalRow {
+    pub host: Option<String>,
+    pub timestamp: String,
+    pub serial: Option<i64>,
+}
+
+impl OriginalRow {
+    /// Identity of `audit_log` as it is stored.
+    pub fn of(audit_log: &parser::AuditLog) -> Self {
+        OriginalRow {
+            host: audit_log.host.clone(),
+            timestamp: audit_log
+                .timestamp
+                .to_rfc3339_opts(SecondsFormat::AutoSi, false),
+            serial: Some(audit_log.serial as i64),
+        }
+    }
+}
+
+impl Original {
+    pub fn encrypt(&self, key: &Key, row: &OriginalRow) -> Result<Vec<u8>> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: &serde_json::to_vec(self)?,
+            aad: &serde_json::to_vec(row)?,
+        };
+        let ciphertext = ChaCha20Poly1305::new(key)
+            .encrypt(&nonce, payload)
+            .map_err(|_| anyhow!("ERROR: Could not encrypt original command"))?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        Ok(sealed)
+    }
+
+    /// Fails unless `sealed` was encrypted with `key` for the same `row`.
+    pub fn decrypt(sealed: &[u8], key: &Key, row: &OriginalRow) -> Result<Original> {
+        if sealed.len() < NONCE_LEN {
+            return Err(anyhow!("ERROR: Encrypted original is truncated"));
+        }
+
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: &serde_json::to_vec(row)?,
+        };
+        let plaintext = ChaCha20Poly1305::new(key)
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .map_err(|_| {
+                anyhow!("ERROR: Could not decrypt original command, wrong REDACT_KEY or row?")
+            })?;
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+}
+
+fn encode_hex(value: &str) -> String {
+    value.bytes().map(|b| format!("{b:02X}")).collect()
+}
+
+/// Value of a field as auditd would log it, hex encoded unless it is plain.
+fn encode_field(value: &str) -> String {
+    if value
+        .chars()
+        .all(|c| c.is_ascii_graphic() && c != '"' && c != '\'')
+    {
+        format!("\"{value}\"")
+    } else {
+        encode_hex(value)
+    }
+}
+
+/// Replaces the value of `key` in a raw record line.
+fn replace_field(line: &str, key: &str, value: &str) -> String {
+    let prefix = format!(" {key}=");
+    let Some(start) = line.find(&prefix).map(|start| start + prefix.len()) else {
+        return line.to_string();
+    };
+    let end = line[start..]
+        .find([' ', '\''])
+        .map_or(line.len(), |len| start + len);
+
+    format!("{}{}{}", &line[..start], value, &line[end..])
+}
+
+/// Removes secrets from command lines before they are stored.
+#[derive(Default)]
+pub struct Redactor {
+    patterns: Vec<RedactPattern>,
+    /// Originals are only kept, encrypted, when a key is set.
+    key: Option<Key>,
+}
+
+impl Redactor {
+    pub fn new(config: RedactConfig, key: Option<Key>) -> Result<Self> {
+        let mut patterns = Vec::new();
+        if config.builtin {
+            for (name, program, regex, next_arg) in BUILTIN_PATTERNS {
+                patterns.push(RedactPattern {
+                    name: name.to_string(),
+                    program: program.map(Regex::new).transpose()?,
+                    regex: Regex::new(regex)?,
+                    next_arg: *next_arg,
+                });
+            }
+        }
+        patterns.extend(config.patterns);
+
+        Ok(Redactor { patterns, key })
+    }
+
+    pub fn patterns(&self) -> &[RedactPattern] {
+        &self.patterns
+    }
+
+    /// Redacts the arguments of `program`, returning whether any changed.
+    pub fn redact_args(&self, program: &str, args: &mut [String]) -> bool {
+        let patterns: Vec<&RedactPattern> = self
+            .patterns
+            .iter()
+            .filter(|pattern| pattern.applies_to(program))
+            .collect();
+
+        let mut changed = false;
+        let mut redact_next = false;
+        for arg in args.iter_mut() {
+            if redact_next {
+                redact_next = false;
+                if arg != REDACTED {
+                    *arg = REDACTED.to_string();
+                    changed = true;
+                }
+                continue;
+            }
+
+            for pattern in &patterns {
+                if pattern.next_arg {
+                    redact_next |= pattern.regex.is_match(arg);
+                } else if let Some(redacted) = pattern.redact(arg) {
+                    *arg = redacted;
+                    changed = true;
+                }
+            }
+        }
+
+        changed
+    }
+
+    /// Redacts the command, arguments and raw records of an exec.
+    ///
+    /// Arguments auditd logged hex encoded are matched decoded, and stored decoded once
+    /// redacted. With a key, the original is kept encrypted.
+    pub fn redact_audit_log(&self, audit_log: &mut parser::AuditLog) -> Result<bool> {
+        let execve = audit_log
+            .raw
+            .iter()
+            .position(|line| line.contains("type=EXECVE "));
+        let record = execve.and_then(|i| AuditRecord::parse(&audit_log.raw[i]).ok());
+        let mut args: Vec<String> = audit_log
+            .args
+            .iter()
+            .enumerate()
+            .map(|(i, arg)| {
+                record
+                    .as_ref()
+                    .and_then(|record| record.field_decoded(&format!("a{}", i + 1)))
+                    .unwrap_or_else(|| arg.clone())
+            })
+            .collect();
+        let decoded = args.clone();
+        let args_redacted = self.redact_args(&audit_log.program, &mut args);
+        // A failed exec logs the command line of its caller.
+        let mut raw = audit_log.raw.clone();
+        if !self.redact_proctitle(&mut raw) && !args_redacted {
+            return Ok(false);
+        }
+
+        let original = Original {
+            command: audit_log.command.clone(),
+            args: audit_log.args.clone(),
+            raw: std::mem::replace(&mut audit_log.raw, raw),
+        };
+        if !args_redacted {
+            if let Some(key) = &self.key {
+                audit_log.original = Some(original.encrypt(key, &OriginalRow::of(audit_log))?);
+            }
+            return Ok(true);
+        }
+
+        for (i, (arg, decoded)) in args.iter().zip(&decoded).enumerate() {
+            if arg == decoded {
+                continue;
+            }
+            audit_log.args[i] = arg.clone();
+            if let Some(execve) = execve {
+                audit_log.raw[execve] = replace_field(
+                    &audit_log.raw[execve],
+                    &format!("a{}", i + 1),
+                    &encode_field(arg),
+                );
+            }
+        }
+        audit_log.command = format!("{} {}", audit_log.program, audit_log.args.join(" "));
+
+        let proctitle = std::iter::once(audit_log.program.as_str())
+            .chain(args.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join("\0");
+        for line in &mut audit_log.raw {
+            if line.contains("type=PROCTITLE ") {
+                *line = replace_field(line, "proctitle", &encode_hex(&proctitle));
+            }
+        }
+
+        if let Some(key) = &self.key {
+            audit_log.original = Some(original.encrypt(key, &OriginalRow::of(audit_log))?);
+        }
+        Ok(true)
+    }
+
+    /// Redacts the command lines in `PROCTITLE` records, split on NUL like the arguments of
+    /// an exec.
+    pub fn redact_proctitle(&self, raw: &mut [String]) -> bool {
+        let mut changed = false;
+        for line in raw
+            .iter_mut()
+            .filter(|line| line.contains("type=PROCTITLE "))
+        {
+            let Some(proctitle) = AuditRecord::parse(line)
+                .ok()
+                .and_then(|record| record.field_decoded("proctitle"))
+            else {
+                continue;
+            };
+            let mut args = proctitle.split('\0').map(String::from);
+            let program = args.next().unwrap_or_default();
+            let mut args: Vec<String> = args.collect();
+            if !self.redact_args(&program, &mut args) {
+                continue;
+            }
+
+            let proctitle = std::iter::once(program)
+                .chain(args)
+                .collect::<Vec<_>>()
+                .join("\0");
+            *line = replace_field(line, "proctitle", &encode_hex(&proctitle));
+            changed = true;
+        }
+
+        changed
+    }
+
+    /// Redacts the command line of the process that accessed the files.
+    pub fn redact_file_event(&self, file_event: &mut parser::FileEvent) -> bool {
+        self.redact_proctitle(&mut file_event.raw)
+    }
+
+    /// Redacts the command a sudo record ran, split on whitespace.
+    pub fn redact_user_command(&self, user_command: &mut parser::UserCommand) -> bool {
+        let mut args: Vec<String> = user_command
+            .command
+            .split_whitespace()
+            .skip(1)
+            .map(String::from)
+            .collect();
+        if !self.redact_args(&user_command.program, &mut args) {
+            return false;
+        }
+
+        user_command.command = format!("{} {}", user_command.program, args.join(" "));
+        for line in &mut user_command.raw {
+            *line = replace_field(line, "cmd", &encode_field(&user_command.command));
+        }
+        true
+    }
+}
@@ -0,0 +1,525 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, VecDeque},
+    fs,
+    path::Path,
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{parser, sigma};
+
+/// Rules shipped with auditor, see `rules/starter.yml`.
+const STARTER_RULES: &str = include_str!("../rules/starter.yml");
+
+/// Upper bound on the events kept for sequence rules, whatever their window.
+const MAX_RECENT_EVENTS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+/// Field of an exec a rule can look at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    /// argv[0], as the caller passed it.
+    Program,
+    /// Path of the executable the kernel ran.
+    Exe,
+    /// Arguments after the program, separated by spaces.
+    Args,
+    Command,
+    /// User name, or the uid when auditd did not resolve it.
+    User,
+    /// Login user name, or the auid when auditd did not resolve it.
+    LoginUser,
+    Cwd,
+    Host,
+    /// Comma separated keys of the audit rules that logged the exec.
+    Key,
+    Pid,
+    Ppid,
+    Ses,
+    /// Program of the latest exec of the parent process.
+    ParentProgram,
+    ParentExe,
+    ParentCommand,
+}
+
+impl Field {
+    /// Whether the field comes from the latest exec of the parent process.
+    pub fn is_parent(&self) -> bool {
+        matches!(
+            self,
+            Field::ParentProgram | Field::ParentExe | Field::ParentCommand
+        )
+    }
+}
+
+/// Conditions on one field, all of which must hold.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FieldMatcher {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub equals: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contains: Option<String>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_regex",
+        deserialize_with = "deserialize_regex"
+    )]
+    pub regex: Option<Regex>,
+}
+
+fn serialize_regex<S: Serializer>(regex: &Option<Regex>, serializer: S) -> Result<S::Ok, S::Error> {
+    regex.as_ref().map(Regex::as_str).serialize(serializer)
+}
+
+fn deserialize_regex<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Regex>, D::Error> {
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+impl FieldMatcher {
+    fn is_empty(&self) -> bool {
+        self.equals.is_none() && self.contains.is_none() && self.regex.is_none()
+    }
+
+    fn matches(&self, value: Option<&str>) -> bool {
+        let Some(value) = value else {
+            return false;
+        };
+
+        self.equals.as_ref().is_none_or(|equals| value == equals)
+            && self
+                .contains
+                .as_ref()
+                .is_none_or(|contains| value.contains(contains.as_str()))
+            && self
+                .regex
+                .as_ref()
+                .is_none_or(|regex| regex.is_match(value))
+    }
+}
+
+/// Field matchers that must all match one exec.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct EventMatcher(pub BTreeMap<Field, FieldMatcher>);
+
+impl EventMatcher {
+    pub fn matches(&self, event: &RuleEvent) -> bool {
+        self.0
+            .iter()
+            .all(|(field, matcher)| matcher.matches(event.value(*field).as_deref()))
+    }
+}
+
+/// A detection rule, matching either a single exec or a sequence of them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub id: String,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub severity: Severity,
+    #[serde(default, rename = "match", skip_serializing_if = "Option::is_none")]
+    pub event: Option<EventMatcher>,
+    /// Execs that must all happen within `within` seconds, the alert is raised on the last.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sequence: Vec<EventMatcher>,
+    /// Whether the steps of the sequence must happen in the given order.
+    #[serde(default = "default_ordered")]
+    pub ordered: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub within: Option<u32>,
+    /// Fields every exec of the sequence must agree on.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub same: Vec<Field>,
+}
+
+fn default_ordered() -> bool {
+    true
+}
+
+impl Rule {
+    fn validate(&self) -> Result<()> {
+        let matchers = self.event.iter().chain(&self.sequence);
+        for matcher in matchers {
+            if matcher.0.is_empty() || matcher.0.values().any(FieldMatcher::is_empty) {
+                return Err(anyhow!("ERROR: Rule '{}' has an empty matcher", self.id));
+            }
+        }
+
+        match (&self.event, self.sequence.len(), self.within) {
+            (Some(_), 0, None) if self.same.is_empty() => Ok(()),
+            (Some(_), _, _) => Err(anyhow!(
+                "ERROR: Rule '{}' mixes match with sequence options",
+                self.id
+            )),
+            (None, 2.., Some(within)) if within > 0 => Ok(()),
+            (None, _, _) => Err(anyhow!(
+                "ERROR: Rule '{}' needs a match or a sequence of at least two steps with within seconds",
+                self.id
+            )),
+        }
+    }
+
+    fn window(&self) -> Duration {
+        Duration::seconds(self.within.unwrap_or(0).into())
+    }
+}
+
+/// Parses a YAML list of rules.
+pub fn parse_rules(yaml: &str) -> Result<Vec<Rule>> {
+    let rules: Vec<Rule> =
+        serde_yaml::from_str(yaml).context("ERROR: Could not parse detection rules")?;
+    for rule in &rules {
+        rule.validate()?;
+    }
+
+    Ok(rules)
+}
+
+/// Loads the starter rules, followed by the rules in `path`, a YAML file or a directory of
+/// them. A rule replaces an earlier one with the same id.
+pub fn load_rules(path: Option<&Path>) -> Result<Vec<Rule>> {
+    let mut rules = parse_rules(STARTER_RULES)?;
+
+    let mut files = Vec::new();
+    match path {
+        Some(path) if path.is_dir() => {
+            for entry in fs::read_dir(path)
+                .context(format!("ERROR: Could not read rules directory {path:?}"))?
+            {
+                let file = entry?.path();
+                if file
+                    .extension()
+                    .is_some_and(|extension| extension == "yml" || extension == "yaml")
+                {
+                    files.push(file);
+                }
+            }
+            files.sort();
+        }
+        Some(path) => files.push(path.to_path_buf()),
+        None => {}
+    }
+
+    for file in files {
+        let yaml = fs::read_to_string(&file)
+            .context(format!("ERROR: Could not read rules file {file:?}"))?;
+        for rule in parse_rules(&yaml).context(format!("ERROR: In rules file {file:?}"))? {
+            match rules.iter_mut().find(|existing| existing.id == rule.id) {
+                Some(existing) => *existing = rule,
+                None => rules.push(rule),
+            }
+        }
+    }
+
+    Ok(rules)
+}
+
+/// The fields of a stored exec that rules look at.
+#[derive(Debug, Clone, Default)]
+pub struct RuleEvent {
+    pub id: i64,
+    pub timestamp: DateTime<Utc>,
+    pub program: String,
+    pub exe: Option<String>,
+    pub args: String,
+    pub command: String,
+    pub user: Option<String>,
+    pub login_user: Option<String>,
+    pub cwd: Option<String>,
+    pub host: Option<String>,
+    pub key: Option<String>,
+    pub pid: Option<i64>,
+    pub ppid: Option<i64>,
+    pub ses: Option<i64>,
+    /// Filled in for every new exec, as the baseline learns parent and child pairs.
+    /// [`crate::sigma::scan`] only looks it up when a rule uses it.
+    pub parent_program: Option<String>,
+    pub parent_exe: Option<String>,
+    pub parent_command: Option<String>,
+}
+
+impl RuleEvent {
+    /// Fields of `audit_log` as stored under row `id`.
+    pub fn new(id: i64, audit_log: &parser::AuditLog) -> Self {
+        RuleEvent {
+            id,
+            timestamp: audit_log.timestamp,
+            program: audit_log.program.clone(),
+            exe: audit_log.exe.clone(),
+            args: audit_log.args.join(" "),
+            command: audit_log.command.clone(),
+            user: audit_log
+                .user
+                .clone()
+                .or(audit_log.uid.map(|uid| uid.to_string())),
+            login_user: audit_log
+                .login_user
+                .clone()
+                .or(audit_log.auid.map(|auid| auid.to_string())),
+            cwd: audit_log.cwd.clone(),
+            host: audit_log.host.clone(),
+            key: audit_log.key.clone(),
+            pid: audit_log.pid,
+            ppid: audit_log.ppid,
+            ses: audit_log.ses,
+            ..Default::default()
+        }
+    }
+
+    pub fn value(&self, field: Field) -> Option<Cow<'_, str>> {
+        match field {
+            Field::Program => Some(Cow::Borrowed(&self.program)),
+            Field::Exe => self.exe.as_deref().map(Cow::Borrowed),
+            Field::Args => Some(Cow::Borrowed(&self.args)),
+            Field::Command => Some(Cow::Borrowed(&self.command)),
+            Field::User => self.user.as_deref().map(Cow::Borrowed),
+            Field::LoginUser => self.login_user.as_deref().map(Cow::Borrowed),
+            Field::Cwd => self.cwd.as_deref().map(Cow::Borrowed),
+            Field::Host => self.host.as_deref().map(Cow::Borrowed),
+            Field::Key => self.key.as_deref().map(Cow::Borrowed),
+            Field::Pid => self.pid.map(|pid| Cow::Owned(pid.to_string())),
+            Field::Ppid => self.ppid.map(|ppid| Cow::Owned(ppid.to_string())),
+            Field::Ses => self.ses.map(|ses| Cow::Owned(ses.to_string())),
+            Field::ParentProgram => self.parent_program.as_deref().map(Cow::Borrowed),
+            Field::ParentExe => self.parent_exe.as_deref().map(Cow::Borrowed),
+            Field::ParentCommand => self.parent_command.as_deref().map(Cow::Borrowed),
+        }
+    }
+}
+
+/// An alert raised by a rule, not stored yet.
+#[derive(Debug, Clone)]
+pub struct NewAlert {
+    pub rule_id: String,
+    pub title: String,
+    pub severity: Severity,
+    /// The exec that completed the match.
+    pub audit_log_id: i64,
+    pub timestamp: DateTime<Utc>,
+    pub host: Option<String>,
+    pub user: Option<String>,
+    pub command: String,
+    /// Earlier execs of a sequence, oldest first.
+    pub related_ids: Vec<i64>,
+}
+
+impl NewAlert {
+    pub(crate) fn new(
+        rule_id: &str,
+        title: &str,
+        severity: Severity,
+        event: &RuleEvent,
+        related_ids: Vec<i64>,
+    ) -> Self {
+        NewAlert {
+            rule_id: rule_id.to_string(),
+            title: title.to_string(),
+            severity,
+            audit_log_id: event.id,
+            timestamp: event.timestamp,
+            host: event.host.clone(),
+            user: event.user.clone(),
+            command: event.command.clone(),
+            related_ids,
+        }
+    }
+}
+
+/// Stored alert.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Alert {
+    pub id: i64,
+    pub rule_id: String,
+    pub title: String,
+    pub severity: String,
+    /// Id of the exec that completed the match, unset once it is deleted.
+    pub audit_log_id: Option<i64>,
+    pub timestamp: String,
+    pub host: Option<String>,
+    pub user: Option<String>,
+    pub command: String,
+    /// Comma separated ids of the earlier execs of a sequence.
+    pub related_ids: Option<String>,
+    pub acknowledged_at: Option<String>,
+    /// Username of whoever acknowledged the alert.
+    pub acknowledged_by: Option<String>,
+}
+
+/// Field filters for the alerts endpoint.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct AlertFilter {
+    /// Inclusive lower bound on the timestamp.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<DateTime<Utc>>,
+    /// Exclusive upper bound on the timestamp.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rule: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub severity: Option<Severity>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub acknowledged: Option<bool>,
+}
+
+/// Evaluates rules against execs as they are stored, remembering recent ones for sequences.
+#[derive(Debug)]
+pub struct RuleEngine {
+    rules: Arc<Vec<Rule>>,
+    sigma_rules: Arc<sigma::SigmaRules>,
+    recent: VecDeque<RuleEvent>,
+    window: Duration,
+}
+
+impl RuleEngine {
+    pub fn new(rules: Arc<Vec<Rule>>) -> Self {
+        let window = rules
+            .iter()
+            .map(Rule::window)
+            .max()
+            .unwrap_or_else(Duration::zero);
+
+        RuleEngine {
+            rules,
+            sigma_rules: Arc::default(),
+            recent: VecDeque::new(),
+            window,
+        }
+    }
+
+    pub fn with_sigma_rules(mut self, sigma_rules: Arc<sigma::SigmaRules>) -> Self {
+        self.sigma_rules = sigma_rules;
+        self
+    }
+
+    /// Alerts raised by `event`, which is remembered for later sequences.
+    pub fn evaluate(&mut self, event: RuleEvent) -> Vec<NewAlert> {
+        let mut alerts = Vec::new();
+        for rule in self.rules.iter() {
+            if let Some(matcher) = &rule.event {
+                if matcher.matches(&event) {
+                    alerts.push(NewAlert::new(
+                        &rule.id,
+                        &rule.title,
+                        rule.severity,
+                        &event,
+                        Vec::new(),
+                    ));
+                }
+            } else if let Some(related_ids) = self.match_sequence(rule, &event) {
+                alerts.push(NewAlert::new(
+                    &rule.id,
+                    &rule.title,
+                    rule.severity,
+                    &event,
+                    related_ids,
+                ));
+            }
+        }
+        alerts.extend(
+            self.sigma_rules
+                .rules
+                .iter()
+                .filter(|rule| rule.matches(&event))
+                .map(|rule| rule.alert(&event)),
+        );
+
+        if self.window > Duration::zero() {
+            let since = event.timestamp - self.window;
+            while self.recent.len() >= MAX_RECENT_EVENTS
+                || self.recent.front().is_some_and(|old| old.timestamp < since)
+            {
+                self.recent.pop_front();
+            }
+            self.recent.push_back(event);
+        }
+
+        alerts
+    }
+
+    /// Ids of the earlier execs completing a sequence that ends with `event`.
+    fn match_sequence(&self, rule: &Rule, event: &RuleEvent) -> Option<Vec<i64>> {
+        let since = event.timestamp - rule.window();
+        // Newest first, so the closest execs are picked.
+        let candidates: Vec<&RuleEvent> = self
+            .recent
+            .iter()
+            .rev()
+            .take_while(|candidate| candidate.timestamp >= since)
+            .filter(|candidate| {
+                rule.same
+                    .iter()
+                    .all(|field| candidate.value(*field) == event.value(*field))
+            })
+            .collect();
+
+        let (last, earlier) = rule.sequence.split_last()?;
+        if rule.ordered {
+            if !last.matches(event) {
+                return None;
+            }
+
+            let mut related = Vec::new();
+            let mut candidates = candidates.into_iter();
+            for step in earlier.iter().rev() {
+                related.push(candidates.find(|candidate| step.matches(candidate))?.id);
+            }
+            related.reverse();
+            return Some(related);
+        }
+
+        (0..rule.sequence.len())
+            .filter(|&i| rule.sequence[i].matches(event))
+            .find_map(|i| {
+                let mut related: Vec<&RuleEvent> = Vec::new();
+                for (j, step) in rule.sequence.iter().enumerate() {
+                    if j == i {
+                        continue;
+                    }
+                    let candidate = candidates.iter().find(|candidate| {
+                        step.matches(candidate)
+                            && related.iter().all(|used| used.id != candidate.id)
+                    })?;
+                    related.push(candidate);
+                }
+                related.sort_by_key(|related| (related.timestamp, related.id));
+                Some(related.iter().map(|related| related.id).collect())
+            })
+    }
+}
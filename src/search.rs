@@ -1,8 +1,89 @@
-use std::iter;
+use std::{
+    collections::{HashMap, HashSet},
+    iter,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
 
 use crate::parser;
 
-fn get_trigrams(s: &str) -> Vec<(char, char, char)> {
+pub const REGEX_MAX_PATTERN_LEN: usize = 512;
+pub const REGEX_SIZE_LIMIT: usize = 1 << 20;
+pub const REGEX_SEARCH_TIMEOUT: Duration = Duration::from_secs(2);
+
+const TOKEN_BONUS_WEIGHT: f32 = 0.4;
+const RECENCY_WEIGHT: f32 = 0.3;
+
+#[derive(Debug, Default)]
+pub struct SearchOptions {
+    pub min_score: f32,
+    /// Age at which the recency boost has halved; `None` disables it.
+    pub recency_half_life: Option<Duration>,
+    /// Collapse identical commands into a single result.
+    pub group: bool,
+    /// Only return logs of the audit rule with this key.
+    pub key: Option<String>,
+}
+
+impl SearchOptions {
+    pub fn matches_key(&self, log: &parser::AuditLogResponse) -> bool {
+        self.key.as_ref().is_none_or(|key| {
+            log.key
+                .as_ref()
+                .is_some_and(|keys| keys.split(',').any(|k| k == key))
+        })
+    }
+}
+
+/// Half life from the `half_life_hours` parameter, `None` for hours that are not positive.
+pub fn parse_half_life(hours: &str) -> Result<Option<Duration>> {
+    let hours: f64 = hours
+        .parse()
+        .map_err(|_| anyhow!("ERROR: half_life_hours must be a number"))?;
+    if !hours.is_finite() {
+        return Err(anyhow!("ERROR: half_life_hours must be finite"));
+    }
+    if hours <= 0.0 {
+        return Ok(None);
+    }
+
+    Duration::try_from_secs_f64(hours * 3600.0)
+        .map(Some)
+        .map_err(|_| anyhow!("ERROR: half_life_hours is too large"))
+}
+
+/// Splits `key:<key>` terms off a fuzzy search query, returning the remaining text and
+/// the last key given.
+pub fn split_key_filter(query: &str) -> (String, Option<String>) {
+    let mut key = None;
+    let mut text = Vec::new();
+    for term in query.split_whitespace() {
+        match term.strip_prefix("key:") {
+            Some(k) if !k.is_empty() => key = Some(k.to_string()),
+            _ => text.push(term),
+        }
+    }
+
+    (text.join(" "), key)
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResult<'a> {
+    #[serde(flatten)]
+    pub log: &'a parser::AuditLogResponse,
+    pub score: f32,
+    /// Half-open `[start, end)` character ranges of `log.command` that matched the query.
+    pub matches: Vec<(usize, usize)>,
+    /// Number of occurrences of `log.command` represented by this result.
+    pub count: usize,
+    pub last_seen: &'a str,
+}
+
+pub(crate) fn get_trigrams(s: &str) -> Vec<(char, char, char)> {
     let it_1 = iter::once(' ').chain(iter::once(' ')).chain(s.chars());
     let it_2 = iter::once(' ').chain(s.chars());
     let it_3 = s.chars().chain(iter::once(' '));
@@ -15,23 +96,48 @@ fn get_trigrams(s: &str) -> Vec<(char, char, char)> {
     res
 }
 
-fn fuzzy_compare(a: &str, b: &str) -> f32 {
-    let string_len = a.chars().count() + 1;
+/// Lowercases `s` one character at a time so that character offsets are preserved.
+pub(crate) fn fold_case(s: &str) -> String {
+    s.chars()
+        .map(|c| c.to_lowercase().next().unwrap_or(c))
+        .collect()
+}
 
-    let trigrams_a = get_trigrams(a);
-    let trigrams_b = get_trigrams(b);
+fn get_tokens(s: &str) -> Vec<&str> {
+    s.split(|c: char| c.is_whitespace() || c == '/' || c == '=')
+        .filter(|token| !token.is_empty())
+        .collect()
+}
 
-    let mut acc: f32 = 0.0f32;
+/// Fraction of the query tokens in `a` that appear as whole tokens in `b`.
+fn token_bonus(a: &str, b: &str) -> f32 {
+    let tokens_a = get_tokens(a);
+    if tokens_a.is_empty() {
+        return 0.0f32;
+    }
 
-    for t_a in &trigrams_a {
-        for t_b in &trigrams_b {
-            if t_a == t_b {
-                acc += 1.0f32;
-                break;
-            }
-        }
+    let tokens_b: HashSet<&str> = get_tokens(b).into_iter().collect();
+    let found = tokens_a.iter().filter(|t| tokens_b.contains(*t)).count();
+
+    found as f32 / tokens_a.len() as f32
+}
+
+pub(crate) fn fuzzy_compare(a: &str, b: &str) -> f32 {
+    if a.trim().is_empty() {
+        return 0.0f32;
     }
-    let res = acc / (string_len as f32);
+
+    let a = fold_case(a);
+    let b = fold_case(b);
+
+    let trigrams_a: HashSet<(char, char, char)> = get_trigrams(&a).into_iter().collect();
+    let trigrams_b: HashSet<(char, char, char)> = get_trigrams(&b).into_iter().collect();
+
+    // Normalizing by both set sizes keeps short queries from matching everything equally.
+    let common = trigrams_a.intersection(&trigrams_b).count();
+    let similarity = common as f32 / ((trigrams_a.len() * trigrams_b.len()) as f32).sqrt();
+
+    let res = similarity * (1.0f32 - TOKEN_BONUS_WEIGHT) + token_bonus(&a, &b) * TOKEN_BONUS_WEIGHT;
 
     if (0.0f32..=1.0f32).contains(&res) {
         res
@@ -40,20 +146,134 @@ fn fuzzy_compare(a: &str, b: &str) -> f32 {
     }
 }
 
+/// Scales `score` down towards `1 - RECENCY_WEIGHT` as `timestamp` gets older than `half_life`.
+pub(crate) fn recency_boost(
+    score: f32,
+    timestamp: &str,
+    now: DateTime<Utc>,
+    half_life: Duration,
+) -> f32 {
+    let Ok(timestamp) = DateTime::parse_from_rfc3339(timestamp) else {
+        return score;
+    };
+
+    let age = (now - timestamp.with_timezone(&Utc)).num_seconds().max(0) as f32;
+    let decay = 0.5f32.powf(age / half_life.as_secs_f32().max(1.0f32));
+
+    score * (1.0f32 - RECENCY_WEIGHT + RECENCY_WEIGHT * decay)
+}
+
+fn merge_ranges(matched: &[bool]) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut start = None;
+
+    for (i, &is_match) in matched.iter().chain(iter::once(&false)).enumerate() {
+        match (is_match, start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                ranges.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+
+    ranges
+}
+
+/// Returns the character ranges of `b` covered by trigrams that also occur in `a`.
+pub(crate) fn fuzzy_match_ranges(a: &str, b: &str) -> Vec<(usize, usize)> {
+    let trigrams_a = get_trigrams(&fold_case(a));
+    let trigrams_b = get_trigrams(&fold_case(b));
+
+    let len = b.chars().count();
+    let mut matched = vec![false; len];
+
+    for (i, t_b) in trigrams_b.iter().enumerate() {
+        if trigrams_a.contains(t_b) {
+            // Trigram `i` spans characters `i - 2..=i` of `b`, padding excluded.
+            for m in matched.iter_mut().take(i + 1).skip(i.saturating_sub(2)) {
+                *m = true;
+            }
+        }
+    }
+
+    merge_ranges(&matched)
+}
+
+/// Returns the character ranges of every match of `regex` in `s`.
+pub fn regex_match_ranges(regex: &Regex, s: &str) -> Vec<(usize, usize)> {
+    regex
+        .find_iter(s)
+        .map(|m| (s[..m.start()].chars().count(), s[..m.end()].chars().count()))
+        .collect()
+}
+
 pub fn fuzzy_search_best_n<'a>(
     s: &'a str,
     list: &'a [parser::AuditLogResponse],
     n: usize,
-) -> Vec<&'a parser::AuditLogResponse> {
-    let mut res: Vec<(&'a parser::AuditLogResponse, f32)> = list
-        .iter()
-        .map(|log| {
-            let score = fuzzy_compare(s, &log.command);
-            (log, score)
+    options: &SearchOptions,
+) -> Vec<SearchResult<'a>> {
+    // Each candidate is the most recent occurrence of a command and its occurrence count.
+    let candidates: Vec<(&'a parser::AuditLogResponse, usize)> = if options.group {
+        let mut groups: HashMap<&'a str, (&'a parser::AuditLogResponse, usize)> = HashMap::new();
+        for log in list.iter().filter(|log| options.matches_key(log)) {
+            let group = groups.entry(&log.command).or_insert((log, 0));
+            if log.timestamp > group.0.timestamp {
+                group.0 = log;
+            }
+            group.1 += 1;
+        }
+        groups.into_values().collect()
+    } else {
+        list.iter().map(|log| (log, 1)).collect()
+    };
+    let candidates = candidates
+        .into_iter()
+        .filter(|(log, _)| options.matches_key(log));
+
+    let now = Utc::now();
+    let mut res: Vec<(&'a parser::AuditLogResponse, usize, f32)> = candidates
+        .map(|(log, count)| {
+            let mut score = fuzzy_compare(s, &log.command);
+            if let Some(half_life) = options.recency_half_life {
+                score = recency_boost(score, &log.timestamp, now, half_life);
+            }
+            (log, count, score)
         })
+        .filter(|(_, _, score)| *score >= options.min_score)
         .collect();
 
-    res.sort_by(|(_, d1), (_, d2)| d2.partial_cmp(d1).unwrap());
+    res.sort_by(|(_, _, d1), (_, _, d2)| d2.partial_cmp(d1).unwrap());
+
+    res.into_iter()
+        .take(n)
+        .map(|(log, count, score)| SearchResult {
+            log,
+            score,
+            matches: fuzzy_match_ranges(s, &log.command),
+            count,
+            last_seen: &log.timestamp,
+        })
+        .collect()
+}
+
+/// Compiles a user supplied search pattern, rejecting patterns that are too long or
+/// would compile to an oversized automaton.
+pub fn build_search_regex(pattern: &str) -> Result<Regex> {
+    if pattern.is_empty() {
+        return Err(anyhow!("ERROR: Regex pattern is empty"));
+    }
+    if pattern.len() > REGEX_MAX_PATTERN_LEN {
+        return Err(anyhow!(
+            "ERROR: Regex pattern exceeds {REGEX_MAX_PATTERN_LEN} bytes"
+        ));
+    }
 
-    res.into_iter().take(n).map(|(log, _)| log).collect()
+    RegexBuilder::new(pattern)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .dfa_size_limit(REGEX_SIZE_LIMIT)
+        .build()
+        .map_err(|err| anyhow!("ERROR: Invalid regex pattern: {err}"))
 }
@@ -1,62 +1,1113 @@
-use std::{collections::HashMap, env, path::PathBuf};
+use std::{
+    collections::HashMap,
+    env,
+    net::{IpAddr, Ipv4Addr},
+    os::unix::fs::FileTypeExt,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
 
-use actix_web::{get, middleware::Logger, web, App, HttpResponse, HttpServer};
+use actix_web::{
+    cookie::{Cookie, SameSite},
+    delete, get,
+    http::header,
+    middleware::Logger,
+    post, web, App, HttpRequest, HttpResponse, HttpServer,
+};
 use env_logger::Env;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
 
-use crate::{db_util, search};
+use crate::{
+    auth, baseline, chain, db_util, export, file_access, index, listen, parser, redact, rules,
+    search, session, sigma, stats, sudo, tree, watcher,
+};
+
+const MAX_PAGE_SIZE: i64 = 1000;
+const STREAM_KEEP_ALIVE: Duration = Duration::from_secs(15);
+const DELETE_CONFIRM_TTL: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Deserialize)]
+struct ListParams {
+    #[serde(default)]
+    order: db_util::SortOrder,
+    cursor: Option<String>,
+    page: Option<i64>,
+    page_size: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+struct AuditLogPage {
+    items: Vec<parser::AuditLogResponse>,
+    total: i64,
+    page_size: i64,
+    next_cursor: Option<String>,
+    order: db_util::SortOrder,
+    filters: db_util::AuditLogFilter,
+}
 
 #[get("/audit_logs")]
 async fn get_audit_logs(
+    _user: auth::AuthUser,
     pool: web::Data<sqlx::SqlitePool>,
-    params: web::Query<HashMap<String, String>>,
+    filter: web::Query<db_util::AuditLogFilter>,
+    params: web::Query<ListParams>,
 ) -> HttpResponse {
-    let page: i64 = params.get("page").and_then(|s| s.parse().ok()).unwrap_or(1);
-    let page_size: i64 = params
-        .get("page_size")
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(10);
+    let filter = filter.into_inner();
+    let page_size = params.page_size.unwrap_or(10).clamp(1, MAX_PAGE_SIZE);
+
+    // A cursor continues after the row it points at, otherwise fall back to page offsets.
+    let (cursor, offset) = match &params.cursor {
+        Some(cursor) => match db_util::Cursor::decode(cursor) {
+            Ok(cursor) => (Some(cursor), 0),
+            Err(err) => return HttpResponse::BadRequest().json(err.to_string()),
+        },
+        None => (None, (params.page.unwrap_or(1).max(1) - 1) * page_size),
+    };
 
-    let audit_logs = match db_util::fetch_audit_logs_paginated(&pool, page, page_size).await {
+    let audit_logs = match db_util::fetch_audit_logs_filtered(
+        &pool,
+        &filter,
+        params.order,
+        cursor.as_ref(),
+        page_size,
+        offset,
+    )
+    .await
+    {
         Ok(audit_logs) => audit_logs,
         Err(err) => return HttpResponse::InternalServerError().json(err.to_string()),
     };
 
-    HttpResponse::Ok().json(audit_logs)
+    let total = match db_util::count_audit_logs(&pool, &filter).await {
+        Ok(total) => total,
+        Err(err) => return HttpResponse::InternalServerError().json(err.to_string()),
+    };
+
+    let next_cursor = if audit_logs.len() as i64 == page_size {
+        audit_logs
+            .last()
+            .and_then(db_util::Cursor::from_response)
+            .map(|cursor| cursor.encode())
+    } else {
+        None
+    };
+
+    HttpResponse::Ok().json(AuditLogPage {
+        items: audit_logs,
+        total,
+        page_size,
+        next_cursor,
+        order: params.order,
+        filters: filter,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportParams {
+    #[serde(default)]
+    format: export::ExportFormat,
+    #[serde(default = "default_export_order")]
+    order: db_util::SortOrder,
+}
+
+fn default_export_order() -> db_util::SortOrder {
+    db_util::SortOrder::Asc
+}
+
+struct ExportState {
+    pool: sqlx::SqlitePool,
+    filter: db_util::AuditLogFilter,
+    params: ExportParams,
+    cursor: Option<db_util::Cursor>,
+    started: bool,
+    finished: bool,
+}
+
+/// Streams every audit log matching the list filters, fetched from the database in chunks.
+#[get("/audit_logs/export")]
+async fn export_audit_logs(
+    _user: auth::AuthUser,
+    pool: web::Data<sqlx::SqlitePool>,
+    filter: web::Query<db_util::AuditLogFilter>,
+    params: web::Query<ExportParams>,
+) -> HttpResponse {
+    let format = params.format;
+    let filename = format!(
+        "auditor-export-{}.{}",
+        chrono::Utc::now().format("%Y%m%dT%H%M%SZ"),
+        format.extension()
+    );
+
+    let state = ExportState {
+        pool: pool.get_ref().clone(),
+        filter: filter.into_inner(),
+        params: params.into_inner(),
+        cursor: None,
+        started: false,
+        finished: false,
+    };
+
+    let stream = futures::stream::unfold(state, |mut state| async move {
+        if state.finished {
+            return None;
+        }
+
+        let format = state.params.format;
+        let audit_logs = match db_util::fetch_audit_log_details_filtered(
+            &state.pool,
+            &state.filter,
+            state.params.order,
+            state.cursor.as_ref(),
+            export::EXPORT_CHUNK_SIZE,
+        )
+        .await
+        {
+            Ok(audit_logs) => audit_logs,
+            Err(err) => {
+                eprintln!("ERROR: Export failed: {err}");
+                state.finished = true;
+                return Some((
+                    Err(actix_web::error::ErrorInternalServerError(err.to_string())),
+                    state,
+                ));
+            }
+        };
+
+        let mut chunk = String::new();
+        if !state.started {
+            chunk.push_str(format.header());
+        }
+        chunk.push_str(&format.encode_chunk(&audit_logs, !state.started));
+        state.started = true;
+
+        match audit_logs.last() {
+            Some(last) if audit_logs.len() as i64 == export::EXPORT_CHUNK_SIZE => {
+                state.cursor = Some(db_util::Cursor {
+                    timestamp: last.timestamp.clone(),
+                    id: last.id,
+                });
+            }
+            _ => {
+                chunk.push_str(format.footer());
+                state.finished = true;
+            }
+        }
+
+        Some((Ok(web::Bytes::from(chunk)), state))
+    });
+
+    HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{filename}\""),
+        ))
+        .streaming(stream)
+}
+
+fn sse_event(event: &str, data: &impl Serialize) -> web::Bytes {
+    let data = serde_json::to_string(data).unwrap_or_default();
+    web::Bytes::from(format!("event: {event}\ndata: {data}\n\n"))
+}
+
+/// Pushes newly stored audit logs matching the list filters as Server-Sent Events.
+#[get("/audit_logs/stream")]
+async fn stream_audit_logs(
+    _user: auth::AuthUser,
+    live_tx: web::Data<broadcast::Sender<watcher::NewAuditLog>>,
+    filter: web::Query<db_util::AuditLogFilter>,
+) -> HttpResponse {
+    let filter = filter.into_inner();
+    let rx = live_tx.subscribe();
+    let mut keep_alive = tokio::time::interval(STREAM_KEEP_ALIVE);
+    keep_alive.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    let stream = futures::stream::unfold(
+        (rx, keep_alive, filter),
+        |(mut rx, mut keep_alive, filter)| async move {
+            loop {
+                let bytes = tokio::select! {
+                    received = rx.recv() => match received {
+                        Ok(new_log) if filter.matches(&new_log.audit_log) => {
+                            sse_event("audit_log", &new_log.audit_log.to_response(new_log.id))
+                        }
+                        Ok(_) => continue,
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            sse_event("lagged", &skipped)
+                        }
+                        Err(broadcast::error::RecvError::Closed) => return None,
+                    },
+                    _ = keep_alive.tick() => web::Bytes::from_static(b": keep-alive\n\n"),
+                };
+
+                return Some((Ok::<_, actix_web::Error>(bytes), (rx, keep_alive, filter)));
+            }
+        },
+    );
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(stream)
+}
+
+#[get("/audit_logs/{id:\\d+}")]
+async fn get_audit_log(
+    _user: auth::AuthUser,
+    pool: web::Data<sqlx::SqlitePool>,
+    id: web::Path<i64>,
+) -> HttpResponse {
+    match db_util::fetch_audit_log(&pool, id.into_inner()).await {
+        Ok(Some(audit_log)) => HttpResponse::Ok().json(audit_log),
+        Ok(None) => HttpResponse::NotFound().json("Audit log not found"),
+        Err(err) => HttpResponse::InternalServerError().json(err.to_string()),
+    }
+}
+
+/// Command, arguments and raw records of a redacted audit log as they were logged.
+#[get("/audit_logs/{id:\\d+}/original")]
+async fn get_audit_log_original(
+    _user: auth::AdminUser,
+    pool: web::Data<sqlx::SqlitePool>,
+    key: web::Data<Option<chacha20poly1305::Key>>,
+    id: web::Path<i64>,
+) -> HttpResponse {
+    let Some(key) = key.as_ref() else {
+        return HttpResponse::NotFound().json("ERROR: Originals are not kept without REDACT_KEY");
+    };
+
+    match db_util::fetch_audit_log_original(&pool, id.into_inner()).await {
+        Ok(Some((sealed, row))) => match redact::Original::decrypt(&sealed, key, &row) {
+            Ok(original) => HttpResponse::Ok().json(original),
+            Err(err) => HttpResponse::InternalServerError().json(err.to_string()),
+        },
+        Ok(None) => HttpResponse::NotFound().json("No original kept for this audit log"),
+        Err(err) => HttpResponse::InternalServerError().json(err.to_string()),
+    }
+}
+
+#[get("/audit_logs/{id:\\d+}/tree")]
+async fn get_process_tree(
+    _user: auth::AuthUser,
+    pool: web::Data<sqlx::SqlitePool>,
+    id: web::Path<i64>,
+) -> HttpResponse {
+    match tree::build_process_tree(&pool, id.into_inner()).await {
+        Ok(Some(process_tree)) => HttpResponse::Ok().json(process_tree),
+        Ok(None) => HttpResponse::NotFound().json("Audit log not found"),
+        Err(err) => HttpResponse::InternalServerError().json(err.to_string()),
+    }
 }
 
 #[get("/audit_logs/search")]
 async fn search_audit_logs(
+    _user: auth::AuthUser,
     pool: web::Data<sqlx::SqlitePool>,
+    search_index: web::Data<RwLock<index::SearchIndex>>,
     params: web::Query<HashMap<String, String>>,
 ) -> HttpResponse {
-    let audit_logs = match db_util::fetch_audit_logs(&pool).await {
-        Ok(audit_logs) => audit_logs,
-        Err(err) => return HttpResponse::InternalServerError().json(err.to_string()),
+    let text = params.get("q").cloned().unwrap_or_default();
+    let regex_mode = params.get("mode").is_some_and(|mode| mode == "regex");
+    // Only fuzzy queries take `key:` terms, a regex is searched for as written.
+    let (query, key) = if regex_mode {
+        (text, None)
+    } else {
+        search::split_key_filter(&text)
     };
-
-    let query = params.get("q").unwrap_or(&String::new()).to_owned();
     let n: usize = params.get("n").and_then(|s| s.parse().ok()).unwrap_or(20);
+    let recency_half_life = match params
+        .get("half_life_hours")
+        .map(|s| search::parse_half_life(s))
+    {
+        Some(Ok(half_life)) => half_life,
+        Some(Err(err)) => return HttpResponse::BadRequest().json(err.to_string()),
+        None => None,
+    };
+    let options = search::SearchOptions {
+        min_score: params
+            .get("min_score")
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(0.0),
+        recency_half_life,
+        group: params.get("group").is_some_and(|group| group == "true"),
+        key: key.or_else(|| params.get("key").cloned()),
+    };
+
+    if regex_mode {
+        let regex = match search::build_search_regex(&query) {
+            Ok(regex) => regex,
+            Err(err) => return HttpResponse::BadRequest().json(err.to_string()),
+        };
+
+        let deadline = Instant::now() + search::REGEX_SEARCH_TIMEOUT;
+        return match db_util::regex_search_audit_logs(
+            &pool,
+            regex.as_str(),
+            options.key.as_deref(),
+            n,
+            deadline,
+        )
+        .await
+        {
+            Ok(audit_logs) => {
+                let results: Vec<search::SearchResult> = audit_logs
+                    .iter()
+                    .map(|log| search::SearchResult {
+                        log,
+                        score: 1.0,
+                        matches: search::regex_match_ranges(&regex, &log.command),
+                        count: 1,
+                        last_seen: &log.timestamp,
+                    })
+                    .collect();
+                HttpResponse::Ok().json(results)
+            }
+            Err(err) if err.is::<db_util::RegexSearchTimeout>() => {
+                HttpResponse::ServiceUnavailable().json(format!(
+                    "Regex search timed out after {}s, try a more specific pattern",
+                    search::REGEX_SEARCH_TIMEOUT.as_secs()
+                ))
+            }
+            Err(err) => HttpResponse::InternalServerError().json(err.to_string()),
+        };
+    }
 
-    let results = search::fuzzy_search_best_n(&query, &audit_logs, n);
+    let search_index = match search_index.read() {
+        Ok(search_index) => search_index,
+        Err(err) => return HttpResponse::InternalServerError().json(err.to_string()),
+    };
+
+    let results = search_index.search(&query, n, &options);
 
     HttpResponse::Ok().json(results)
 }
 
-#[get("/audit_logs/clear")]
-async fn clear_audit_logs(pool: web::Data<sqlx::SqlitePool>) -> HttpResponse {
-    match db_util::clear_audit_logs(&pool).await {
-        Ok(result) => result,
+/// Key of the deletion confirmation tokens, only valid until the server restarts.
+struct ConfirmSecret(String);
+
+#[derive(Debug, Deserialize)]
+struct DeleteParams {
+    /// Exclusive upper bound on the timestamp, combined with `to` if both are given.
+    before: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    dry_run: bool,
+    confirm: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct DeletionPreviewResponse {
+    count: i64,
+    confirm: String,
+    expires_in: i64,
+    filters: db_util::AuditLogFilter,
+}
+
+#[derive(Debug, Serialize)]
+struct DeletionResponse {
+    deleted: usize,
+    filters: db_util::AuditLogFilter,
+}
+
+/// Deletes the audit logs matching the filters.
+///
+/// A dry run reports how many rows would be deleted along with a confirmation token,
+/// which must be sent back in `confirm` before anything is deleted.
+#[delete("/audit_logs")]
+async fn delete_audit_logs(
+    auth::AdminUser(user): auth::AdminUser,
+    req: HttpRequest,
+    pool: web::Data<sqlx::SqlitePool>,
+    search_index: web::Data<RwLock<index::SearchIndex>>,
+    filter: web::Query<db_util::AuditLogFilter>,
+    params: web::Query<DeleteParams>,
+    secret: web::Data<ConfirmSecret>,
+) -> HttpResponse {
+    let mut filter = filter.into_inner();
+    if let Some(before) = params.before {
+        filter.to = Some(filter.to.map_or(before, |to| to.min(before)));
+    }
+    if filter.is_empty() {
+        return HttpResponse::BadRequest()
+            .json("ERROR: Refusing to delete without 'before' or a filter");
+    }
+
+    let preview = match db_util::preview_deletion(&pool, &filter).await {
+        Ok(preview) => preview,
+        Err(err) => return HttpResponse::InternalServerError().json(err.to_string()),
+    };
+
+    let now = chrono::Utc::now().timestamp();
+    let confirmed = params.confirm.as_ref().is_some_and(|token| {
+        preview.verify(
+            &filter,
+            token,
+            now,
+            DELETE_CONFIRM_TTL.as_secs() as i64,
+            secret.0.as_bytes(),
+        )
+    });
+    if params.dry_run || !confirmed {
+        let response = DeletionPreviewResponse {
+            count: preview.count,
+            confirm: preview.confirmation_token(&filter, now, secret.0.as_bytes()),
+            expires_in: DELETE_CONFIRM_TTL.as_secs() as i64,
+            filters: filter,
+        };
+        return match (params.dry_run, &params.confirm) {
+            (true, _) => HttpResponse::Ok().json(response),
+            // The matching rows changed since the token was issued, or it expired.
+            (false, Some(_)) => HttpResponse::Conflict().json(response),
+            (false, None) => HttpResponse::PreconditionRequired().json(response),
+        };
+    }
+
+    let Some(max_id) = preview.max_id else {
+        return HttpResponse::Ok().json(DeletionResponse {
+            deleted: 0,
+            filters: filter,
+        });
+    };
+
+    let remote_addr = req.connection_info().realip_remote_addr().map(String::from);
+    let ids = match db_util::delete_audit_logs(
+        &pool,
+        &filter,
+        max_id,
+        Some(&user.username),
+        remote_addr.as_deref(),
+    )
+    .await
+    {
+        Ok(ids) => ids,
+        Err(err) => return HttpResponse::InternalServerError().json(err.to_string()),
+    };
+
+    match search_index.write() {
+        Ok(mut search_index) => search_index.remove(&ids),
+        Err(err) => return HttpResponse::InternalServerError().json(err.to_string()),
+    };
+
+    println!(
+        "INFO: {} deleted {} audit logs matching {}",
+        user.username,
+        ids.len(),
+        serde_json::to_string(&filter).unwrap_or_default()
+    );
+
+    HttpResponse::Ok().json(DeletionResponse {
+        deleted: ids.len(),
+        filters: filter,
+    })
+}
+
+#[get("/audit_logs/deletions")]
+async fn get_audit_log_deletions(
+    _user: auth::AdminUser,
+    pool: web::Data<sqlx::SqlitePool>,
+) -> HttpResponse {
+    match db_util::fetch_audit_log_deletions(&pool, MAX_PAGE_SIZE).await {
+        Ok(deletions) => HttpResponse::Ok().json(deletions),
+        Err(err) => HttpResponse::InternalServerError().json(err.to_string()),
+    }
+}
+
+/// Walks the hash chain over the stored audit logs, reporting any tampering.
+#[get("/chain/verify")]
+async fn verify_chain(
+    _user: auth::AdminUser,
+    pool: web::Data<sqlx::SqlitePool>,
+    key: web::Data<Option<ed25519_dalek::VerifyingKey>>,
+) -> HttpResponse {
+    match chain::verify(&pool, key.as_ref().as_ref()).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(err) => HttpResponse::InternalServerError().json(err.to_string()),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PageParams {
+    page: Option<i64>,
+    page_size: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+struct SessionPage {
+    items: Vec<session::Session>,
+    total: i64,
+    page_size: i64,
+    filters: db_util::AuditLogFilter,
+}
+
+#[get("/sessions")]
+async fn get_sessions(
+    _user: auth::AuthUser,
+    pool: web::Data<sqlx::SqlitePool>,
+    filter: web::Query<db_util::AuditLogFilter>,
+    params: web::Query<PageParams>,
+) -> HttpResponse {
+    let filter = filter.into_inner();
+    let page_size = params.page_size.unwrap_or(10).clamp(1, MAX_PAGE_SIZE);
+    let offset = (params.page.unwrap_or(1).max(1) - 1) * page_size;
+
+    let sessions = match db_util::fetch_sessions(&pool, &filter, page_size, offset).await {
+        Ok(sessions) => sessions,
+        Err(err) => return HttpResponse::InternalServerError().json(err.to_string()),
+    };
+
+    let total = match db_util::count_sessions(&pool, &filter).await {
+        Ok(total) => total,
+        Err(err) => return HttpResponse::InternalServerError().json(err.to_string()),
+    };
+
+    HttpResponse::Ok().json(SessionPage {
+        items: sessions,
+        total,
+        page_size,
+        filters: filter,
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct LoginPage {
+    items: Vec<crate::login::Login>,
+    total: i64,
+    page_size: i64,
+    filters: crate::login::LoginFilter,
+}
+
+#[get("/logins")]
+async fn get_logins(
+    _user: auth::AuthUser,
+    pool: web::Data<sqlx::SqlitePool>,
+    filter: web::Query<crate::login::LoginFilter>,
+    params: web::Query<PageParams>,
+) -> HttpResponse {
+    let filter = filter.into_inner();
+    let page_size = params.page_size.unwrap_or(10).clamp(1, MAX_PAGE_SIZE);
+    let offset = (params.page.unwrap_or(1).max(1) - 1) * page_size;
+
+    let logins = match db_util::fetch_logins(&pool, &filter, page_size, offset).await {
+        Ok(logins) => logins,
+        Err(err) => return HttpResponse::InternalServerError().json(err.to_string()),
+    };
+
+    let total = match db_util::count_logins(&pool, &filter).await {
+        Ok(total) => total,
+        Err(err) => return HttpResponse::InternalServerError().json(err.to_string()),
+    };
+
+    HttpResponse::Ok().json(LoginPage {
+        items: logins,
+        total,
+        page_size,
+        filters: filter,
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct SudoPage {
+    items: Vec<sudo::SudoCommand>,
+    total: i64,
+    page_size: i64,
+    filters: sudo::SudoFilter,
+}
+
+#[get("/sudo")]
+async fn get_sudo_commands(
+    _user: auth::AuthUser,
+    pool: web::Data<sqlx::SqlitePool>,
+    filter: web::Query<sudo::SudoFilter>,
+    params: web::Query<PageParams>,
+) -> HttpResponse {
+    let filter = filter.into_inner();
+    let page_size = params.page_size.unwrap_or(10).clamp(1, MAX_PAGE_SIZE);
+    let offset = (params.page.unwrap_or(1).max(1) - 1) * page_size;
+
+    let sudo_commands = match db_util::fetch_sudo_commands(&pool, &filter, page_size, offset).await
+    {
+        Ok(sudo_commands) => sudo_commands,
+        Err(err) => return HttpResponse::InternalServerError().json(err.to_string()),
+    };
+
+    let total = match db_util::count_sudo_commands(&pool, &filter).await {
+        Ok(total) => total,
+        Err(err) => return HttpResponse::InternalServerError().json(err.to_string()),
+    };
+
+    HttpResponse::Ok().json(SudoPage {
+        items: sudo_commands,
+        total,
+        page_size,
+        filters: filter,
+    })
+}
+
+#[get("/rules")]
+async fn get_rules(_user: auth::AuthUser, rules: web::Data<Vec<rules::Rule>>) -> HttpResponse {
+    HttpResponse::Ok().json(rules.as_ref())
+}
+
+/// Translated Sigma rules along with the ones that could not be translated.
+#[get("/sigma")]
+async fn get_sigma_rules(
+    _user: auth::AuthUser,
+    sigma_rules: web::Data<sigma::SigmaRules>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(sigma_rules.as_ref())
+}
+
+#[derive(Debug, Serialize)]
+struct SigmaScanResponse {
+    scanned: u64,
+    alerts: u64,
+}
+
+/// Evaluates the Sigma rules against every stored exec, raising alerts missed so far.
+#[post("/sigma/scan")]
+async fn scan_sigma_rules(
+    _user: auth::AdminUser,
+    pool: web::Data<sqlx::SqlitePool>,
+    sigma_rules: web::Data<sigma::SigmaRules>,
+) -> HttpResponse {
+    match sigma::scan(&pool, &sigma_rules.rules).await {
+        Ok((scanned, alerts)) => HttpResponse::Ok().json(SigmaScanResponse { scanned, alerts }),
+        Err(err) => HttpResponse::InternalServerError().json(err.to_string()),
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct AlertPage {
+    items: Vec<rules::Alert>,
+    total: i64,
+    page_size: i64,
+    filters: rules::AlertFilter,
+}
+
+#[get("/alerts")]
+async fn get_alerts(
+    _user: auth::AuthUser,
+    pool: web::Data<sqlx::SqlitePool>,
+    filter: web::Query<rules::AlertFilter>,
+    params: web::Query<PageParams>,
+) -> HttpResponse {
+    let filter = filter.into_inner();
+    let page_size = params.page_size.unwrap_or(10).clamp(1, MAX_PAGE_SIZE);
+    let offset = (params.page.unwrap_or(1).max(1) - 1) * page_size;
+
+    let alerts = match db_util::fetch_alerts(&pool, &filter, page_size, offset).await {
+        Ok(alerts) => alerts,
+        Err(err) => return HttpResponse::InternalServerError().json(err.to_string()),
+    };
+
+    let total = match db_util::count_alerts(&pool, &filter).await {
+        Ok(total) => total,
+        Err(err) => return HttpResponse::InternalServerError().json(err.to_string()),
+    };
+
+    HttpResponse::Ok().json(AlertPage {
+        items: alerts,
+        total,
+        page_size,
+        filters: filter,
+    })
+}
+
+/// Marks an alert as handled by the current user, keeping the first acknowledgement.
+#[post("/alerts/{id:\\d+}/ack")]
+async fn acknowledge_alert(
+    auth::AuthUser(user): auth::AuthUser,
+    pool: web::Data<sqlx::SqlitePool>,
+    id: web::Path<i64>,
+) -> HttpResponse {
+    let id = id.into_inner();
+    if let Err(err) = db_util::acknowledge_alert(&pool, id, &user.username).await {
+        return HttpResponse::InternalServerError().json(err.to_string());
+    }
+
+    match db_util::fetch_alert(&pool, id).await {
+        Ok(Some(alert)) => HttpResponse::Ok().json(alert),
+        Ok(None) => HttpResponse::NotFound().json("Alert not found"),
+        Err(err) => HttpResponse::InternalServerError().json(err.to_string()),
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct BaselinePage {
+    items: Vec<baseline::BaselineEntry>,
+    total: i64,
+    page_size: i64,
+    filters: baseline::BaselineFilter,
+}
+
+#[get("/baseline")]
+async fn get_baseline(
+    _user: auth::AuthUser,
+    pool: web::Data<sqlx::SqlitePool>,
+    filter: web::Query<baseline::BaselineFilter>,
+    params: web::Query<PageParams>,
+) -> HttpResponse {
+    let filter = filter.into_inner();
+    let page_size = params.page_size.unwrap_or(10).clamp(1, MAX_PAGE_SIZE);
+    let offset = (params.page.unwrap_or(1).max(1) - 1) * page_size;
+
+    let entries = match db_util::fetch_baseline(&pool, &filter, page_size, offset).await {
+        Ok(entries) => entries,
+        Err(err) => return HttpResponse::InternalServerError().json(err.to_string()),
+    };
+
+    let total = match db_util::count_baseline(&pool, &filter).await {
+        Ok(total) => total,
+        Err(err) => return HttpResponse::InternalServerError().json(err.to_string()),
+    };
+
+    HttpResponse::Ok().json(BaselinePage {
+        items: entries,
+        total,
+        page_size,
+        filters: filter,
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct FileAccessPage {
+    items: Vec<file_access::FileAccess>,
+    total: i64,
+    page_size: i64,
+    filters: file_access::FileAccessFilter,
+}
+
+#[get("/file_access")]
+async fn get_file_accesses(
+    _user: auth::AuthUser,
+    pool: web::Data<sqlx::SqlitePool>,
+    filter: web::Query<file_access::FileAccessFilter>,
+    params: web::Query<PageParams>,
+) -> HttpResponse {
+    let filter = filter.into_inner();
+    let page_size = params.page_size.unwrap_or(10).clamp(1, MAX_PAGE_SIZE);
+    let offset = (params.page.unwrap_or(1).max(1) - 1) * page_size;
+
+    let file_accesses = match db_util::fetch_file_accesses(&pool, &filter, page_size, offset).await
+    {
+        Ok(file_accesses) => file_accesses,
+        Err(err) => return HttpResponse::InternalServerError().json(err.to_string()),
+    };
+
+    let total = match db_util::count_file_accesses(&pool, &filter).await {
+        Ok(total) => total,
+        Err(err) => return HttpResponse::InternalServerError().json(err.to_string()),
+    };
+
+    HttpResponse::Ok().json(FileAccessPage {
+        items: file_accesses,
+        total,
+        page_size,
+        filters: filter,
+    })
+}
+
+#[get("/file_access/keys")]
+async fn get_file_access_keys(
+    _user: auth::AuthUser,
+    pool: web::Data<sqlx::SqlitePool>,
+) -> HttpResponse {
+    match db_util::fetch_file_access_keys(&pool).await {
+        Ok(keys) => HttpResponse::Ok().json(keys),
+        Err(err) => HttpResponse::InternalServerError().json(err.to_string()),
+    }
+}
+
+#[get("/sessions/{id}")]
+async fn get_session(
+    _user: auth::AuthUser,
+    pool: web::Data<sqlx::SqlitePool>,
+    id: web::Path<String>,
+) -> HttpResponse {
+    let key = match session::decode_session_id(&id) {
+        Ok(key) => key,
+        Err(err) => return HttpResponse::BadRequest().json(err.to_string()),
+    };
+
+    match db_util::fetch_session(&pool, &key).await {
+        Ok(Some(session)) => HttpResponse::Ok().json(session),
+        Ok(None) => HttpResponse::NotFound().json("Session not found"),
+        Err(err) => HttpResponse::InternalServerError().json(err.to_string()),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TopParams {
+    n: Option<i64>,
+}
+
+#[get("/stats/summary")]
+async fn get_stats_summary(
+    _user: auth::AuthUser,
+    pool: web::Data<sqlx::SqlitePool>,
+    filter: web::Query<db_util::AuditLogFilter>,
+) -> HttpResponse {
+    match db_util::fetch_summary(&pool, &filter).await {
+        Ok(summary) => HttpResponse::Ok().json(summary),
+        Err(err) => HttpResponse::InternalServerError().json(err.to_string()),
+    }
+}
+
+#[get("/stats/top/{dimension}")]
+async fn get_stats_top(
+    _user: auth::AuthUser,
+    pool: web::Data<sqlx::SqlitePool>,
+    dimension: web::Path<stats::TopDimension>,
+    filter: web::Query<db_util::AuditLogFilter>,
+    params: web::Query<TopParams>,
+) -> HttpResponse {
+    let n = params.n.unwrap_or(10).clamp(1, MAX_PAGE_SIZE);
+
+    match db_util::fetch_top_counts(&pool, dimension.into_inner(), &filter, n).await {
+        Ok(top) => HttpResponse::Ok().json(top),
+        Err(err) => HttpResponse::InternalServerError().json(err.to_string()),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct HistogramParams {
+    #[serde(default)]
+    bucket: stats::HistogramBucket,
+}
+
+#[get("/stats/histogram")]
+async fn get_stats_histogram(
+    _user: auth::AuthUser,
+    pool: web::Data<sqlx::SqlitePool>,
+    filter: web::Query<db_util::AuditLogFilter>,
+    params: web::Query<HistogramParams>,
+) -> HttpResponse {
+    let rows = match db_util::fetch_histogram_counts(&pool, params.bucket, &filter).await {
+        Ok(rows) => rows,
+        Err(err) => return HttpResponse::InternalServerError().json(err.to_string()),
+    };
+
+    match stats::fill_histogram(rows, params.bucket) {
+        Ok(histogram) => HttpResponse::Ok().json(histogram),
+        Err(err) => HttpResponse::BadRequest().json(err.to_string()),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct LoginRequest {
+    username: String,
+    password: String,
+}
+
+fn session_cookie(req: &HttpRequest, value: String, max_age: chrono::Duration) -> Cookie<'static> {
+    Cookie::build(auth::SESSION_COOKIE, value)
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .secure(req.connection_info().scheme() == "https")
+        .max_age(actix_web::cookie::time::Duration::seconds(
+            max_age.num_seconds(),
+        ))
+        .finish()
+}
+
+#[post("/login")]
+async fn login(
+    req: HttpRequest,
+    pool: web::Data<sqlx::SqlitePool>,
+    throttle: web::Data<auth::LoginThrottle>,
+    body: web::Json<LoginRequest>,
+) -> HttpResponse {
+    let addr = req
+        .peer_addr()
+        .map_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED), |addr| addr.ip());
+    if let Some(retry_after) = throttle.retry_after(addr) {
+        return HttpResponse::TooManyRequests()
+            .insert_header((header::RETRY_AFTER, retry_after.as_secs().max(1)))
+            .json("ERROR: Too many failed logins, try again later");
+    }
+
+    let user = match db_util::fetch_user_by_username(&pool, &body.username).await {
+        Ok(user) => user,
+        Err(err) => return HttpResponse::InternalServerError().json(err.to_string()),
+    };
+
+    let password = body.password.clone();
+    let password_hash = user.as_ref().map(|user| user.password_hash.clone());
+    let verified =
+        match web::block(move || auth::verify_login(&password, password_hash.as_deref())).await {
+            Ok(verified) => verified,
+            Err(err) => return HttpResponse::InternalServerError().json(err.to_string()),
+        };
+
+    let user = match user {
+        Some(user) if verified => user,
+        _ => {
+            throttle.record_failure(addr);
+            eprintln!(
+                "ERROR: Failed login for user '{}' from {addr}",
+                body.username
+            );
+            return HttpResponse::Unauthorized().json("ERROR: Invalid username or password");
+        }
+    };
+    throttle.record_success(addr);
+
+    let user = match auth::User::try_from(user) {
+        Ok(user) => user,
         Err(err) => return HttpResponse::InternalServerError().json(err.to_string()),
     };
 
-    println!("INFO: Audit logs older than 2 weeks cleared successfully");
+    let session = auth::generate_token();
+    let expires_at = chrono::Utc::now() + auth::SESSION_TTL;
+    if let Err(err) =
+        db_util::insert_session(&pool, &auth::hash_token(&session), user.id, expires_at).await
+    {
+        return HttpResponse::InternalServerError().json(err.to_string());
+    }
+
+    println!("INFO: User '{}' logged in", user.username);
+
+    HttpResponse::Ok()
+        .cookie(session_cookie(&req, session, auth::SESSION_TTL))
+        .json(user)
+}
+
+#[post("/logout")]
+async fn logout(req: HttpRequest, pool: web::Data<sqlx::SqlitePool>) -> HttpResponse {
+    if let Some(cookie) = req.cookie(auth::SESSION_COOKIE) {
+        if let Err(err) = db_util::delete_session(&pool, &auth::hash_token(cookie.value())).await {
+            return HttpResponse::InternalServerError().json(err.to_string());
+        }
+    }
+
+    HttpResponse::Ok()
+        .cookie(session_cookie(
+            &req,
+            String::new(),
+            chrono::Duration::zero(),
+        ))
+        .json("Logged out")
+}
+
+#[get("/me")]
+async fn get_me(auth::AuthUser(user): auth::AuthUser) -> HttpResponse {
+    HttpResponse::Ok().json(user)
+}
+
+#[derive(Debug, Deserialize)]
+struct NewApiToken {
+    name: String,
+}
 
-    HttpResponse::Ok().body("Audit logs older than 2 weeks cleared successfully")
+#[derive(Debug, Serialize)]
+struct CreatedApiToken {
+    #[serde(flatten)]
+    api_token: db_util::ApiToken,
+    /// Only returned once, the database keeps a hash.
+    token: String,
+}
+
+#[get("/tokens")]
+async fn get_api_tokens(
+    auth::AuthUser(user): auth::AuthUser,
+    pool: web::Data<sqlx::SqlitePool>,
+) -> HttpResponse {
+    match db_util::fetch_api_tokens(&pool, user.id).await {
+        Ok(api_tokens) => HttpResponse::Ok().json(api_tokens),
+        Err(err) => HttpResponse::InternalServerError().json(err.to_string()),
+    }
+}
+
+#[post("/tokens")]
+async fn create_api_token(
+    auth::AuthUser(user): auth::AuthUser,
+    pool: web::Data<sqlx::SqlitePool>,
+    body: web::Json<NewApiToken>,
+) -> HttpResponse {
+    let token = auth::generate_token();
+
+    match db_util::insert_api_token(&pool, user.id, &body.name, &auth::hash_token(&token)).await {
+        Ok(api_token) => HttpResponse::Created().json(CreatedApiToken { api_token, token }),
+        Err(err) => HttpResponse::InternalServerError().json(err.to_string()),
+    }
+}
+
+#[delete("/tokens/{id}")]
+async fn delete_api_token(
+    auth::AuthUser(user): auth::AuthUser,
+    pool: web::Data<sqlx::SqlitePool>,
+    id: web::Path<i64>,
+) -> HttpResponse {
+    let id = id.into_inner();
+
+    match db_util::delete_api_token(&pool, id, user.id).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => HttpResponse::NotFound().json(format!("ERROR: Token {id} not found")),
+        Err(err) => HttpResponse::InternalServerError().json(err.to_string()),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct NewUser {
+    username: String,
+    password: String,
+    role: auth::Role,
+}
+
+#[get("/users")]
+async fn get_users(_user: auth::AdminUser, pool: web::Data<sqlx::SqlitePool>) -> HttpResponse {
+    let users = match db_util::fetch_users(&pool).await {
+        Ok(users) => users,
+        Err(err) => return HttpResponse::InternalServerError().json(err.to_string()),
+    };
+
+    match users
+        .into_iter()
+        .map(auth::User::try_from)
+        .collect::<anyhow::Result<Vec<_>>>()
+    {
+        Ok(users) => HttpResponse::Ok().json(users),
+        Err(err) => HttpResponse::InternalServerError().json(err.to_string()),
+    }
+}
+
+#[post("/users")]
+async fn create_user(
+    _user: auth::AdminUser,
+    pool: web::Data<sqlx::SqlitePool>,
+    body: web::Json<NewUser>,
+) -> HttpResponse {
+    if body.username.is_empty() || body.password.is_empty() {
+        return HttpResponse::BadRequest().json("ERROR: Username and password are required");
+    }
+
+    let password = body.password.clone();
+    let password_hash = match web::block(move || auth::hash_password(&password)).await {
+        Ok(Ok(password_hash)) => password_hash,
+        Ok(Err(err)) => return HttpResponse::InternalServerError().json(err.to_string()),
+        Err(err) => return HttpResponse::InternalServerError().json(err.to_string()),
+    };
+
+    match db_util::insert_user(&pool, &body.username, &password_hash, body.role.as_str()).await {
+        Ok(id) => HttpResponse::Created().json(auth::User {
+            id,
+            username: body.username.clone(),
+            role: body.role,
+        }),
+        Err(err) => HttpResponse::Conflict().json(err.to_string()),
+    }
 }
 
 pub async fn run_server(
-    port: u32,
+    listen_addrs: Vec<listen::ListenAddr>,
+    tls: Option<listen::TlsConfig>,
     db_pool: sqlx::Pool<sqlx::Sqlite>,
+    search_index: Arc<RwLock<index::SearchIndex>>,
+    live_tx: broadcast::Sender<watcher::NewAuditLog>,
+    rules: Arc<Vec<rules::Rule>>,
+    sigma_rules: Arc<sigma::SigmaRules>,
 ) -> anyhow::Result<actix_web::dev::Server> {
     env_logger::init_from_env(Env::default().default_filter_or("debug"));
 
@@ -69,23 +1120,98 @@ pub async fn run_server(
         static_dir = "./static".into();
     }
 
+    let tls_config = match tls {
+        Some(tls) => {
+            let resolver = Arc::new(listen::ReloadingCertResolver::new(tls)?);
+            tokio::spawn(listen::watch_certificates(resolver.clone()));
+            Some(listen::server_config(resolver)?)
+        }
+        None => None,
+    };
+
+    let redact_key = redact::load_key()?;
+    let chain_key = chain::load_verifying_key()?;
+    let confirm_secret = web::Data::new(ConfirmSecret(auth::generate_token()));
+    let login_throttle = web::Data::new(auth::LoginThrottle::default());
+
     println!("INFO: Starting server");
 
-    Ok(HttpServer::new(move || {
+    let mut server = HttpServer::new(move || {
         App::new()
             .wrap(Logger::new(
                 "%a \"%r\" %s %b %D \"%{Referer}i\" \"%{User-Agent}i\" %U %{r}a",
             ))
             .app_data(web::Data::new(db_pool.clone()))
+            .app_data(web::Data::from(search_index.clone()))
+            .app_data(web::Data::new(live_tx.clone()))
+            .app_data(web::Data::from(rules.clone()))
+            .app_data(web::Data::from(sigma_rules.clone()))
+            .app_data(web::Data::new(redact_key))
+            .app_data(web::Data::new(chain_key))
+            .app_data(confirm_secret.clone())
+            .app_data(login_throttle.clone())
             .service(
                 web::scope("/api")
+                    .service(login)
+                    .service(logout)
+                    .service(get_me)
+                    .service(get_api_tokens)
+                    .service(create_api_token)
+                    .service(delete_api_token)
+                    .service(get_users)
+                    .service(create_user)
                     .service(get_audit_logs)
                     .service(search_audit_logs)
-                    .service(clear_audit_logs),
+                    .service(delete_audit_logs)
+                    .service(get_audit_log_deletions)
+                    .service(verify_chain)
+                    .service(stream_audit_logs)
+                    .service(export_audit_logs)
+                    .service(get_audit_log)
+                    .service(get_audit_log_original)
+                    .service(get_process_tree)
+                    .service(get_sessions)
+                    .service(get_session)
+                    .service(get_logins)
+                    .service(get_sudo_commands)
+                    .service(get_file_access_keys)
+                    .service(get_file_accesses)
+                    .service(get_rules)
+                    .service(get_sigma_rules)
+                    .service(scan_sigma_rules)
+                    .service(get_alerts)
+                    .service(acknowledge_alert)
+                    .service(get_baseline)
+                    .service(get_stats_summary)
+                    .service(get_stats_top)
+                    .service(get_stats_histogram),
             )
             .service(actix_files::Files::new("/", static_dir.clone()).index_file("index.html"))
             .default_service(web::route().to(HttpResponse::NotFound))
-    })
-    .bind(format!("127.0.0.1:{port}"))?
-    .run())
+    });
+
+    for listen_addr in listen_addrs {
+        server = match (&listen_addr, &tls_config) {
+            (listen::ListenAddr::Tcp(addr), Some(tls_config)) => {
+                server.bind_rustls_0_23(addr, tls_config.clone())?
+            }
+            (listen::ListenAddr::Tcp(addr), None) => server.bind(addr)?,
+            (listen::ListenAddr::Unix(path), _) => {
+                // A socket left behind by a previous run would make the bind fail.
+                if std::fs::metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+                    std::fs::remove_file(path)?;
+                }
+                server.bind_uds(path)?
+            }
+        };
+
+        let scheme = match (&listen_addr, &tls_config) {
+            (listen::ListenAddr::Tcp(_), Some(_)) => "https",
+            (listen::ListenAddr::Tcp(_), None) => "http",
+            (listen::ListenAddr::Unix(_), _) => "unix",
+        };
+        println!("INFO: Listening on {listen_addr} ({scheme})");
+    }
+
+    Ok(server.run())
 }
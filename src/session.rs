@@ -0,0 +1,71 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+use crate::parser;
+
+/// Commands returned for a single session at most.
+pub const MAX_SESSION_COMMANDS: i64 = 10_000;
+
+/// Login session, grouping the commands run on a host with the same audit session id and
+/// tty since the last login, session start or end recorded for it.
+///
+/// Audit session ids restart after a reboot, so the login is what tells sessions apart.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Session {
+    /// `<ses>:<login>:<tty>@<host>`, without `@<host>` for logs without a node name.
+    pub id: String,
+    pub host: Option<String>,
+    pub ses: i64,
+    pub tty: Option<String>,
+    pub auid: Option<i64>,
+    pub login_user: Option<String>,
+    /// Remote address of the login that opened the session, if it was recorded.
+    pub source: Option<String>,
+    pub start: String,
+    pub end: String,
+    pub commands: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionDetail {
+    #[serde(flatten)]
+    pub session: Session,
+    /// Commands of the session in the order they ran.
+    pub history: Vec<parser::AuditLogResponse>,
+}
+
+/// What tells a session apart, decoded from its id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionKey {
+    pub ses: i64,
+    /// Id of the auth event the session started after, 0 for commands before any.
+    pub login: i64,
+    pub tty: Option<String>,
+    pub host: Option<String>,
+}
+
+/// Splits a session id into its audit session id, login, tty and host.
+pub fn decode_session_id(id: &str) -> Result<SessionKey> {
+    let invalid = || anyhow!("ERROR: Invalid session id '{id}'");
+    let (key, host) = match id.split_once('@') {
+        Some((key, host)) => (key, Some(host.to_string())),
+        None => (id, None),
+    };
+    let mut parts = key.splitn(3, ':');
+    let ses = parts
+        .next()
+        .and_then(|ses| ses.parse().ok())
+        .ok_or_else(invalid)?;
+    let login = parts
+        .next()
+        .and_then(|login| login.parse().ok())
+        .ok_or_else(invalid)?;
+    let tty = parts.next().ok_or_else(invalid)?.to_string();
+
+    Ok(SessionKey {
+        ses,
+        login,
+        tty: Some(tty).filter(|tty| !tty.is_empty()),
+        host,
+    })
+}
@@ -0,0 +1,565 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+
+use crate::{
+    db_util,
+    rules::{Field, NewAlert, RuleEvent, Severity},
+};
+
+/// Stored execs evaluated per query when scanning the database.
+const SCAN_BATCH_SIZE: i64 = 1000;
+
+/// Sigma `process_creation` field names and the fields they map to.
+const FIELD_MAPPING: &[(&str, Field)] = &[
+    ("Image", Field::Exe),
+    ("CommandLine", Field::Command),
+    ("User", Field::User),
+    ("CurrentDirectory", Field::Cwd),
+    ("ParentImage", Field::ParentExe),
+    ("ParentCommandLine", Field::ParentCommand),
+    ("ProcessId", Field::Pid),
+    ("ParentProcessId", Field::Ppid),
+    ("LogonId", Field::Ses),
+];
+
+/// Test of one field from a selection, e.g. `CommandLine|contains|all: [...]`.
+#[derive(Debug, Clone)]
+struct FieldTest {
+    field: Field,
+    /// `None` matches a missing or empty field.
+    patterns: Vec<Option<Regex>>,
+    all: bool,
+}
+
+impl FieldTest {
+    fn matches(&self, event: &RuleEvent) -> bool {
+        let value = event.value(self.field);
+        let matches = |pattern: &Option<Regex>| match pattern {
+            Some(regex) => value.as_deref().is_some_and(|value| regex.is_match(value)),
+            None => value.as_deref().is_none_or(str::is_empty),
+        };
+
+        if self.all {
+            self.patterns.iter().all(matches)
+        } else {
+            self.patterns.iter().any(matches)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Condition {
+    /// Every test must match.
+    Tests(Vec<FieldTest>),
+    Not(Box<Condition>),
+    And(Vec<Condition>),
+    Or(Vec<Condition>),
+}
+
+impl Condition {
+    fn matches(&self, event: &RuleEvent) -> bool {
+        match self {
+            Condition::Tests(tests) => tests.iter().all(|test| test.matches(event)),
+            Condition::Not(condition) => !condition.matches(event),
+            Condition::And(conditions) => conditions.iter().all(|c| c.matches(event)),
+            Condition::Or(conditions) => conditions.iter().any(|c| c.matches(event)),
+        }
+    }
+
+    fn uses_parent(&self) -> bool {
+        match self {
+            Condition::Tests(tests) => tests.iter().any(|test| test.field.is_parent()),
+            Condition::Not(condition) => condition.uses_parent(),
+            Condition::And(conditions) | Condition::Or(conditions) => {
+                conditions.iter().any(Condition::uses_parent)
+            }
+        }
+    }
+}
+
+/// A Sigma rule translated into tests over the fields of stored execs.
+#[derive(Debug, Clone, Serialize)]
+pub struct SigmaRule {
+    pub id: String,
+    pub title: String,
+    pub severity: Severity,
+    pub path: PathBuf,
+    #[serde(skip)]
+    condition: Condition,
+}
+
+impl SigmaRule {
+    pub fn matches(&self, event: &RuleEvent) -> bool {
+        self.condition.matches(event)
+    }
+
+    pub fn uses_parent(&self) -> bool {
+        self.condition.uses_parent()
+    }
+
+    pub fn alert(&self, event: &RuleEvent) -> NewAlert {
+        NewAlert::new(&self.id, &self.title, self.severity, event, Vec::new())
+    }
+}
+
+/// A Sigma rule that could not be translated, and why.
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedRule {
+    pub path: PathBuf,
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub reason: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SigmaRules {
+    pub rules: Vec<SigmaRule>,
+    pub skipped: Vec<SkippedRule>,
+}
+
+impl SigmaRules {
+    /// Translates every rule in `yaml`, which may hold several documents.
+    pub fn add_yaml(&mut self, yaml: &str, path: &Path) {
+        for document in serde_yaml::Deserializer::from_str(yaml) {
+            let rule = match Value::deserialize(document) {
+                Ok(rule) => rule,
+                Err(err) => {
+                    self.skip(path, &Value::Null, format!("invalid YAML: {err}"));
+                    break;
+                }
+            };
+            if rule.is_null() {
+                continue;
+            }
+
+            match translate(&rule, path) {
+                Ok(rule) => self.rules.push(rule),
+                Err(reason) => self.skip(path, &rule, reason),
+            }
+        }
+    }
+
+    fn skip(&mut self, path: &Path, rule: &Value, reason: String) {
+        let text = |key: &str| rule.get(key).and_then(Value::as_str).map(String::from);
+        self.skipped.push(SkippedRule {
+            path: path.to_path_buf(),
+            id: text("id"),
+            title: text("title"),
+            reason,
+        });
+    }
+}
+
+/// Loads the Sigma rules in `path`, a YAML file or a directory searched recursively.
+pub fn load_sigma_rules(path: &Path) -> Result<SigmaRules> {
+    let mut files = Vec::new();
+    collect_files(path, &mut files)?;
+    files.sort();
+
+    let mut sigma_rules = SigmaRules::default();
+    for file in files {
+        let yaml = fs::read_to_string(&file)
+            .context(format!("ERROR: Could not read Sigma rule {file:?}"))?;
+        sigma_rules.add_yaml(&yaml, &file);
+    }
+
+    Ok(sigma_rules)
+}
+
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+
+    for entry in
+        fs::read_dir(path).context(format!("ERROR: Could not read Sigma directory {path:?}"))?
+    {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension == "yml" || extension == "yaml")
+        {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+fn translate(rule: &Value, path: &Path) -> Result<SigmaRule, String> {
+    if rule.get("action").is_some() {
+        return Err(String::from("rule collections are not supported"));
+    }
+    let text = |key: &str| rule.get(key).and_then(Value::as_str);
+
+    let logsource = rule.get("logsource");
+    let logsource = |key: &str| logsource.and_then(|l| l.get(key)).and_then(Value::as_str);
+    if logsource("product") != Some("linux") || logsource("category") != Some("process_creation") {
+        return Err(String::from(
+            "only linux process_creation rules are supported",
+        ));
+    }
+
+    let id = text("id").ok_or("missing id")?;
+    let title = text("title").ok_or("missing title")?;
+    let severity = match text("level") {
+        Some("informational" | "low") | None => Severity::Low,
+        Some("medium") => Severity::Medium,
+        Some("high") => Severity::High,
+        Some("critical") => Severity::Critical,
+        Some(level) => return Err(format!("unknown level '{level}'")),
+    };
+
+    let detection = rule
+        .get("detection")
+        .and_then(Value::as_mapping)
+        .ok_or("missing detection")?;
+    let mut selections = BTreeMap::new();
+    let mut conditions = Vec::new();
+    for (name, value) in detection {
+        let name = name.as_str().ok_or("detection keys must be strings")?;
+        match name {
+            "condition" => match value {
+                Value::String(condition) => conditions.push(condition.clone()),
+                Value::Sequence(list) => {
+                    for condition in list {
+                        let condition = condition.as_str().ok_or("condition must be a string")?;
+                        conditions.push(condition.to_string());
+                    }
+                }
+                _ => return Err(String::from("condition must be a string")),
+            },
+            "timeframe" => return Err(String::from("timeframe is not supported")),
+            _ => {
+                selections.insert(
+                    name.to_string(),
+                    selection(value).map_err(|err| format!("selection '{name}': {err}"))?,
+                );
+            }
+        }
+    }
+    if conditions.is_empty() {
+        return Err(String::from("missing condition"));
+    }
+
+    let mut parsed = Vec::new();
+    for condition in &conditions {
+        parsed.push(parse_condition(condition, &selections)?);
+    }
+    let condition = match parsed.len() {
+        1 => parsed.remove(0),
+        _ => Condition::Or(parsed),
+    };
+
+    Ok(SigmaRule {
+        id: id.to_string(),
+        title: title.to_string(),
+        severity,
+        path: path.to_path_buf(),
+        condition,
+    })
+}
+
+/// A selection is a map of field tests, a list of such maps, or a list of keywords.
+fn selection(value: &Value) -> Result<Condition, String> {
+    match value {
+        Value::Mapping(_) => field_tests(value),
+        Value::Sequence(list) if list.iter().all(Value::is_mapping) => Ok(Condition::Or(
+            list.iter().map(field_tests).collect::<Result<_, _>>()?,
+        )),
+        Value::Sequence(list) => {
+            // Keywords are searched for anywhere in the command line.
+            let patterns = list
+                .iter()
+                .map(|keyword| {
+                    let keyword = scalar(keyword).ok_or("keywords must be strings")?;
+                    wildcard_regex(&keyword, "contains", false).map(Some)
+                })
+                .collect::<Result<_, String>>()?;
+            Ok(Condition::Tests(vec![FieldTest {
+                field: Field::Command,
+                patterns,
+                all: false,
+            }]))
+        }
+        _ => Err(String::from("must be a map or a list")),
+    }
+}
+
+fn field_tests(value: &Value) -> Result<Condition, String> {
+    let mapping = value.as_mapping().ok_or("must be a map")?;
+
+    let mut tests = Vec::new();
+    for (key, value) in mapping {
+        let key = key.as_str().ok_or("field names must be strings")?;
+        let mut parts = key.split('|');
+        let name = parts.next().unwrap_or_default();
+        let field = FIELD_MAPPING
+            .iter()
+            .find(|(sigma_name, _)| *sigma_name == name)
+            .map(|(_, field)| *field)
+            .ok_or(format!("field '{name}' is not available"))?;
+
+        let mut operator = "equals";
+        let mut all = false;
+        let mut cased = false;
+        let mut exists = false;
+        for modifier in parts {
+            match modifier {
+                "contains" | "startswith" | "endswith" | "re" => operator = modifier,
+                "all" => all = true,
+                "cased" => cased = true,
+                "exists" => exists = true,
+                _ => return Err(format!("modifier '{modifier}' is not supported")),
+            }
+        }
+
+        if exists {
+            let expected = value.as_bool().ok_or("exists takes true or false")?;
+            // Matching nothing but the empty value negated is "exists".
+            let test = FieldTest {
+                field,
+                patterns: vec![None],
+                all: false,
+            };
+            tests.push(if expected {
+                Condition::Not(Box::new(Condition::Tests(vec![test])))
+            } else {
+                Condition::Tests(vec![test])
+            });
+            continue;
+        }
+
+        let values = match value {
+            Value::Sequence(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        let mut patterns = Vec::new();
+        for value in values {
+            if value.is_null() {
+                patterns.push(None);
+                continue;
+            }
+            let value = scalar(value).ok_or(format!("value of '{key}' must be a string"))?;
+            patterns.push(Some(wildcard_regex(&value, operator, cased)?));
+        }
+        tests.push(Condition::Tests(vec![FieldTest {
+            field,
+            patterns,
+            all,
+        }]));
+    }
+
+    Ok(Condition::And(tests))
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Number(value) => Some(value.to_string()),
+        Value::Bool(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// Regex for a Sigma value, where `*` and `?` are wildcards unless escaped with `\`.
+fn wildcard_regex(value: &str, operator: &str, cased: bool) -> Result<Regex, String> {
+    if operator == "re" {
+        return Regex::new(value).map_err(|err| format!("invalid regex: {err}"));
+    }
+
+    let mut pattern = String::from(if cased { "" } else { "(?i)" });
+    if !matches!(operator, "contains" | "endswith") {
+        pattern.push('^');
+    }
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            '\\' if matches!(chars.peek(), Some('*' | '?' | '\\')) => {
+                pattern.push_str(&regex::escape(&chars.next().unwrap_or('\\').to_string()));
+            }
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    if !matches!(operator, "contains" | "startswith") {
+        pattern.push('$');
+    }
+
+    Regex::new(&pattern).map_err(|err| format!("invalid value '{value}': {err}"))
+}
+
+/// Parses a condition such as `selection and not 1 of filter_*`.
+fn parse_condition(
+    condition: &str,
+    selections: &BTreeMap<String, Condition>,
+) -> Result<Condition, String> {
+    if condition.contains('|') {
+        return Err(String::from("aggregations are not supported"));
+    }
+
+    let spaced = condition.replace('(', " ( ").replace(')', " ) ");
+    let tokens: Vec<&str> = spaced.split_whitespace().collect();
+    let mut parser = ConditionParser {
+        tokens: &tokens,
+        position: 0,
+        selections,
+    };
+    let parsed = parser.or()?;
+    match parser.tokens.get(parser.position) {
+        Some(token) => Err(format!("unexpected '{token}' in condition")),
+        None => Ok(parsed),
+    }
+}
+
+struct ConditionParser<'a> {
+    tokens: &'a [&'a str],
+    position: usize,
+    selections: &'a BTreeMap<String, Condition>,
+}
+
+impl<'a> ConditionParser<'a> {
+    fn next(&mut self) -> Option<&'a str> {
+        let token = self.tokens.get(self.position).copied();
+        self.position += 1;
+        token
+    }
+
+    fn peek_is(&self, keyword: &str) -> bool {
+        self.tokens
+            .get(self.position)
+            .is_some_and(|token| token.eq_ignore_ascii_case(keyword))
+    }
+
+    fn or(&mut self) -> Result<Condition, String> {
+        let mut conditions = vec![self.and()?];
+        while self.peek_is("or") {
+            self.position += 1;
+            conditions.push(self.and()?);
+        }
+        Ok(match conditions.len() {
+            1 => conditions.remove(0),
+            _ => Condition::Or(conditions),
+        })
+    }
+
+    fn and(&mut self) -> Result<Condition, String> {
+        let mut conditions = vec![self.not()?];
+        while self.peek_is("and") {
+            self.position += 1;
+            conditions.push(self.not()?);
+        }
+        Ok(match conditions.len() {
+            1 => conditions.remove(0),
+            _ => Condition::And(conditions),
+        })
+    }
+
+    fn not(&mut self) -> Result<Condition, String> {
+        if self.peek_is("not") {
+            self.position += 1;
+            return Ok(Condition::Not(Box::new(self.not()?)));
+        }
+
+        match self.next() {
+            Some("(") => {
+                let condition = self.or()?;
+                match self.next() {
+                    Some(")") => Ok(condition),
+                    _ => Err(String::from("unbalanced parentheses in condition")),
+                }
+            }
+            Some(quantifier @ ("1" | "any" | "all")) => {
+                if !self.peek_is("of") {
+                    return Err(format!("expected 'of' after '{quantifier}'"));
+                }
+                self.position += 1;
+                let target = self.next().ok_or("missing selection after 'of'")?;
+                let matched = self.matching(target)?;
+                Ok(if quantifier == "all" {
+                    Condition::And(matched)
+                } else {
+                    Condition::Or(matched)
+                })
+            }
+            Some(name) => self
+                .selections
+                .get(name)
+                .cloned()
+                .ok_or(format!("unknown selection '{name}'")),
+            None => Err(String::from("condition ended unexpectedly")),
+        }
+    }
+
+    /// Selections named by `them` or a pattern like `selection_*`.
+    fn matching(&self, target: &str) -> Result<Vec<Condition>, String> {
+        let matched: Vec<Condition> = if target == "them" {
+            self.selections
+                .iter()
+                .filter(|(name, _)| !name.starts_with('_'))
+                .map(|(_, condition)| condition.clone())
+                .collect()
+        } else {
+            let regex = wildcard_regex(target, "equals", true)?;
+            self.selections
+                .iter()
+                .filter(|(name, _)| regex.is_match(name))
+                .map(|(_, condition)| condition.clone())
+                .collect()
+        };
+
+        if matched.is_empty() {
+            return Err(format!("no selection matches '{target}'"));
+        }
+        Ok(matched)
+    }
+}
+
+/// Evaluates `rules` against every stored exec, storing alerts not raised before.
+///
+/// Returns how many execs were scanned and how many new alerts were raised.
+pub async fn scan(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    rules: &[SigmaRule],
+) -> anyhow::Result<(u64, u64)> {
+    let with_parent = rules.iter().any(SigmaRule::uses_parent);
+    let mut after_id = 0;
+    let mut scanned = 0;
+    let mut raised = 0;
+
+    loop {
+        let events =
+            db_util::fetch_rule_events(pool, after_id, SCAN_BATCH_SIZE, with_parent).await?;
+        let Some(last) = events.last() else {
+            break;
+        };
+        after_id = last.id;
+        scanned += events.len() as u64;
+
+        for event in &events {
+            for rule in rules.iter().filter(|rule| rule.matches(event)) {
+                if db_util::insert_alert(pool, &rule.alert(event))
+                    .await?
+                    .rows_affected()
+                    > 0
+                {
+                    raised += 1;
+                }
+            }
+        }
+    }
+
+    Ok((scanned, raised))
+}
@@ -0,0 +1,139 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Refuse histograms that would need more buckets than this once gaps are filled.
+pub const MAX_HISTOGRAM_BUCKETS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TopDimension {
+    Programs,
+    Commands,
+    Users,
+    Hosts,
+    Keys,
+}
+
+impl TopDimension {
+    /// SQL expression grouped on, rows without a value are counted as `(unknown)`.
+    pub fn column(&self) -> &'static str {
+        match self {
+            TopDimension::Programs => "program",
+            TopDimension::Commands => "command",
+            TopDimension::Users => "COALESCE(user, CAST(uid AS TEXT), '(unknown)')",
+            TopDimension::Hosts => "COALESCE(host, '(unknown)')",
+            TopDimension::Keys => "COALESCE(audit_log_key.key, '(none)')",
+        }
+    }
+
+    /// Join counting a row once per value, for rows holding several.
+    pub fn join(&self) -> &'static str {
+        match self {
+            TopDimension::Keys => {
+                " LEFT JOIN audit_log_key ON audit_log_key.audit_log_id = audit_log.id"
+            }
+            _ => "",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, sqlx::FromRow)]
+pub struct TopEntry {
+    pub key: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HistogramBucket {
+    Minute,
+    #[default]
+    Hour,
+    Day,
+}
+
+impl HistogramBucket {
+    /// Length of the timestamp prefix identifying a bucket, e.g. `2024-05-29T17` for hours.
+    pub fn prefix_len(&self) -> i64 {
+        match self {
+            HistogramBucket::Minute => 16,
+            HistogramBucket::Hour => 13,
+            HistogramBucket::Day => 10,
+        }
+    }
+
+    fn duration(&self) -> Duration {
+        match self {
+            HistogramBucket::Minute => Duration::minutes(1),
+            HistogramBucket::Hour => Duration::hours(1),
+            HistogramBucket::Day => Duration::days(1),
+        }
+    }
+
+    fn parse_prefix(&self, prefix: &str) -> Result<DateTime<Utc>> {
+        let padded = match self {
+            HistogramBucket::Minute => format!("{prefix}:00"),
+            HistogramBucket::Hour => format!("{prefix}:00:00"),
+            HistogramBucket::Day => format!("{prefix}T00:00:00"),
+        };
+
+        NaiveDateTime::parse_from_str(&padded, "%Y-%m-%dT%H:%M:%S")
+            .map(|timestamp| timestamp.and_utc())
+            .map_err(|err| anyhow!("ERROR: Invalid histogram bucket '{prefix}': {err}"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HistogramEntry {
+    pub start: DateTime<Utc>,
+    pub count: i64,
+}
+
+/// Turns `(bucket prefix, count)` rows into contiguous buckets, filling gaps with zero.
+pub fn fill_histogram(
+    rows: Vec<(String, i64)>,
+    bucket: HistogramBucket,
+) -> Result<Vec<HistogramEntry>> {
+    let mut counts = rows
+        .into_iter()
+        .map(|(prefix, count)| Ok((bucket.parse_prefix(&prefix)?, count)))
+        .collect::<Result<Vec<_>>>()?;
+    counts.sort_unstable();
+
+    let (first, last) = match (counts.first(), counts.last()) {
+        (Some((first, _)), Some((last, _))) => (*first, *last),
+        _ => return Ok(Vec::new()),
+    };
+
+    let len = ((last - first).num_seconds() / bucket.duration().num_seconds()) as usize + 1;
+    if len > MAX_HISTOGRAM_BUCKETS {
+        return Err(anyhow!(
+            "ERROR: Histogram would have {len} buckets, use a larger bucket or a shorter window"
+        ));
+    }
+
+    let mut histogram = Vec::with_capacity(len);
+    let mut counts = counts.into_iter().peekable();
+    let mut start = first;
+    while start <= last {
+        let count = match counts.next_if(|(timestamp, _)| *timestamp == start) {
+            Some((_, count)) => count,
+            None => 0,
+        };
+        histogram.push(HistogramEntry { start, count });
+        start += bucket.duration();
+    }
+
+    Ok(histogram)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, sqlx::FromRow)]
+pub struct Summary {
+    pub total: i64,
+    pub programs: i64,
+    pub users: i64,
+    pub hosts: i64,
+    pub first_seen: Option<String>,
+    pub last_seen: Option<String>,
+}
@@ -0,0 +1,51 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// How long after sudo logged a command its exec may follow to be linked to it.
+pub const SUDO_LINK_WINDOW: Duration = Duration::seconds(5);
+
+/// Stored sudo command, see [`crate::parser::UserCommand`].
+///
+/// The target user comes from the linked exec, and is unknown while none is linked.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct SudoCommand {
+    pub id: i64,
+    pub timestamp: String,
+    pub host: Option<String>,
+    pub pid: Option<i64>,
+    pub uid: Option<i64>,
+    pub auid: Option<i64>,
+    pub ses: Option<i64>,
+    pub login_user: Option<String>,
+    pub cwd: Option<String>,
+    pub command: String,
+    pub terminal: Option<String>,
+    pub success: bool,
+    /// Id of the exec of the command.
+    pub audit_log_id: Option<i64>,
+    pub run_as_uid: Option<i64>,
+    pub run_as_user: Option<String>,
+    /// Set when the command ran as another user than the one logged in.
+    pub privilege_escalation: Option<bool>,
+}
+
+/// Field filters for the sudo endpoint.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct SudoFilter {
+    /// Inclusive lower bound on the timestamp.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<DateTime<Utc>>,
+    /// Exclusive upper bound on the timestamp.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<DateTime<Utc>>,
+    /// Real user name or numeric auid of whoever ran sudo.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// User name or numeric uid the command ran as.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run_as: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub privilege_escalation: Option<bool>,
+}
@@ -0,0 +1,108 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::db_util;
+
+/// Stop walking up or down the tree after this many generations.
+pub const MAX_TREE_DEPTH: usize = 16;
+/// Stop collecting descendants once this many were found.
+pub const MAX_TREE_NODES: usize = 500;
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ProcessEvent {
+    pub id: i64,
+    pub timestamp: String,
+    pub command: String,
+    pub host: Option<String>,
+    pub pid: Option<i64>,
+    pub ppid: Option<i64>,
+    pub user: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProcessNode {
+    #[serde(flatten)]
+    pub event: ProcessEvent,
+    pub children: Vec<ProcessNode>,
+    /// Set when the depth or node limit cut off further descendants.
+    pub truncated: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProcessTree {
+    /// Ancestors of the event, starting with the oldest one found.
+    pub ancestors: Vec<ProcessEvent>,
+    pub tree: ProcessNode,
+}
+
+/// Rebuilds the ancestry and descendants of a stored event from pid/ppid.
+///
+/// The parent of an event is the latest earlier exec whose pid is its ppid on the same
+/// host. Its children are the execs whose ppid is its pid, up to the next exec of that pid.
+pub async fn build_process_tree(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    id: i64,
+) -> Result<Option<ProcessTree>> {
+    let Some(event) = db_util::fetch_process_event(pool, id).await? else {
+        return Ok(None);
+    };
+
+    let mut ancestors = Vec::new();
+    let mut seen = HashSet::from([event.id]);
+    let mut current = event.clone();
+    while ancestors.len() < MAX_TREE_DEPTH {
+        match db_util::fetch_process_parent(pool, &current).await? {
+            Some(parent) if seen.insert(parent.id) => {
+                ancestors.push(parent.clone());
+                current = parent;
+            }
+            _ => break,
+        }
+    }
+    ancestors.reverse();
+
+    let mut children: HashMap<i64, Vec<ProcessEvent>> = HashMap::new();
+    let mut truncated = HashSet::new();
+    let mut nodes = 1;
+    let mut queue = VecDeque::from([(event.clone(), 0)]);
+    while let Some((parent, depth)) = queue.pop_front() {
+        if depth >= MAX_TREE_DEPTH || nodes >= MAX_TREE_NODES {
+            truncated.insert(parent.id);
+            continue;
+        }
+
+        let limit = (MAX_TREE_NODES - nodes) as i64;
+        let found = db_util::fetch_process_children(pool, &parent, limit).await?;
+        for child in &found {
+            if seen.insert(child.id) {
+                nodes += 1;
+                queue.push_back((child.clone(), depth + 1));
+            }
+        }
+        children.insert(parent.id, found);
+    }
+
+    Ok(Some(ProcessTree {
+        ancestors,
+        tree: assemble(event, &mut children, &truncated),
+    }))
+}
+
+fn assemble(
+    event: ProcessEvent,
+    children: &mut HashMap<i64, Vec<ProcessEvent>>,
+    truncated: &HashSet<i64>,
+) -> ProcessNode {
+    let found = children.remove(&event.id).unwrap_or_default();
+
+    ProcessNode {
+        truncated: truncated.contains(&event.id),
+        children: found
+            .into_iter()
+            .map(|child| assemble(child, children, truncated))
+            .collect(),
+        event,
+    }
+}
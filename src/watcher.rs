@@ -2,21 +2,38 @@ use std::{
     fs::File,
     io::{Read, Seek, SeekFrom},
     path::Path,
+    sync::{Arc, RwLock},
     thread,
     time::Duration,
 };
 
 use futures::{
     channel::mpsc::{channel, Receiver},
-    lock::Mutex,
     SinkExt, StreamExt,
 };
 use notify::{
     event::{DataChange, ModifyKind},
     Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher,
 };
+use tokio::sync::broadcast;
 
-use crate::{db_util, parser};
+use crate::{baseline, db_util, index, notifier, parser, redact, rules};
+
+pub const LIVE_CHANNEL_CAPACITY: usize = 1024;
+
+/// An audit log that was just stored under row `id`, published to live subscribers.
+#[derive(Debug, Clone)]
+pub struct NewAuditLog {
+    pub id: i64,
+    pub audit_log: Arc<parser::AuditLog>,
+}
+
+/// What newly stored execs are checked against, and where the alerts they raise go.
+pub struct Detection {
+    pub rule_engine: rules::RuleEngine,
+    pub baseline: baseline::Baseline,
+    pub notifications: notifier::Notifications,
+}
 
 fn async_watcher() -> notify::Result<(RecommendedWatcher, Receiver<notify::Result<Event>>)> {
     let (mut tx, rx) = channel(1);
@@ -33,10 +50,98 @@ fn async_watcher() -> notify::Result<(RecommendedWatcher, Receiver<notify::Resul
     Ok((watcher, rx))
 }
 
+async fn store_audit_log(
+    db_pool: &sqlx::Pool<sqlx::Sqlite>,
+    search_index: &RwLock<index::SearchIndex>,
+    live_tx: &broadcast::Sender<NewAuditLog>,
+    detection: &mut Detection,
+    audit_log: &parser::AuditLog,
+) {
+    match db_util::insert_audit_log(db_pool, audit_log).await {
+        // Already stored when the log is read again.
+        Ok(result) if result.rows_affected() == 0 => {}
+        Ok(result) => {
+            let id = result.last_insert_rowid();
+            if let Err(e) = db_util::link_user_command(db_pool, id, audit_log).await {
+                eprintln!("{e}");
+            }
+            match search_index.write() {
+                Ok(mut search_index) => search_index.insert(audit_log.to_response(id)),
+                Err(e) => eprintln!("ERROR: Could not update search index: {e}"),
+            }
+            // The baseline learns parent and child pairs, whether or not a rule needs them.
+            let mut rule_event = rules::RuleEvent::new(id, audit_log);
+            match db_util::fetch_exec_parent(db_pool, id).await {
+                Ok(Some(parent)) => {
+                    rule_event.parent_program = Some(parent.program);
+                    rule_event.parent_exe = parent.exe;
+                    rule_event.parent_command = Some(parent.command);
+                }
+                Ok(None) => {}
+                Err(e) => eprintln!("{e}"),
+            }
+            let mut alerts = match detection.baseline.observe(db_pool, &rule_event).await {
+                Ok(alerts) => alerts,
+                Err(e) => {
+                    eprintln!("{e}");
+                    Vec::new()
+                }
+            };
+            alerts.extend(detection.rule_engine.evaluate(rule_event));
+            for alert in alerts {
+                println!(
+                    "INFO: Rule {} raised a {} alert for audit log {id}",
+                    alert.rule_id,
+                    alert.severity.as_str()
+                );
+                match db_util::insert_alert(db_pool, &alert).await {
+                    Ok(result) if result.rows_affected() > 0 => detection.notifications.notify(
+                        notifier::Notification::new(result.last_insert_rowid(), &alert),
+                    ),
+                    Ok(_) => {}
+                    Err(e) => eprintln!("{e}"),
+                }
+            }
+
+            // Sending only fails when nobody is subscribed.
+            let _ = live_tx.send(NewAuditLog {
+                id,
+                audit_log: Arc::new(audit_log.clone()),
+            });
+        }
+        Err(insert_error) => println!("ERROR: Could not insert new audit log: {insert_error}"),
+    }
+}
+
+async fn store_auth_event(db_pool: &sqlx::Pool<sqlx::Sqlite>, auth_event: &parser::AuthEvent) {
+    if let Err(insert_error) = db_util::insert_auth_event(db_pool, auth_event).await {
+        println!("ERROR: Could not insert new auth event: {insert_error}");
+    }
+}
+
+async fn store_user_command(
+    db_pool: &sqlx::Pool<sqlx::Sqlite>,
+    user_command: &parser::UserCommand,
+) {
+    if let Err(insert_error) = db_util::insert_user_command(db_pool, user_command).await {
+        println!("ERROR: Could not insert new sudo command: {insert_error}");
+    }
+}
+
+async fn store_file_event(db_pool: &sqlx::Pool<sqlx::Sqlite>, file_event: &parser::FileEvent) {
+    if let Err(insert_error) = db_util::insert_file_event(db_pool, file_event).await {
+        println!("ERROR: Could not insert new file access: {insert_error}");
+    }
+}
+
 pub async fn async_watch<P: AsRef<Path>>(
     path: P,
     stream_position: u64,
     db_pool: sqlx::Pool<sqlx::Sqlite>,
+    search_index: Arc<RwLock<index::SearchIndex>>,
+    live_tx: broadcast::Sender<NewAuditLog>,
+    mut detection: Detection,
+    redactor: redact::Redactor,
 ) -> notify::Result<()> {
     println!("INFO: Starting watcher");
 
@@ -46,7 +151,7 @@ pub async fn async_watch<P: AsRef<Path>>(
 
     let mut position: u64 = stream_position;
 
-    let last_audit_log_mutex: Mutex<Option<parser::AuditLog>> = Mutex::new(None);
+    let mut assembler = parser::EventAssembler::default();
 
     while let Some(res) = rx.next().await {
         match res {
@@ -60,37 +165,56 @@ pub async fn async_watch<P: AsRef<Path>>(
                     let mut string = String::new();
                     file.read_to_string(&mut string)?;
 
-                    for line in string.lines() {
-                        match parser::AuditLog::parse_line(line) {
-                            Ok(audit_log) => {
-                                let mut last_audit_log = last_audit_log_mutex.lock().await;
-                                if last_audit_log.is_none() {
-                                    *last_audit_log = Some(audit_log);
-                                } else if last_audit_log
-                                    .clone()
-                                    .is_some_and(|lal| audit_log.timestamp > lal.timestamp)
-                                {
-                                    // println!("INFO: Parsed line: {audit_log}");
-
-                                    if let Err(insert_error) =
-                                        db_util::insert_audit_log(&db_pool, &audit_log).await
-                                    {
-                                        println!(
-                                            "ERROR: Could not insert new audit log: {insert_error}"
-                                        );
-                                    }
-
-                                    *last_audit_log = Some(audit_log);
-
-                                    position = file.stream_position()?;
-                                }
-                            }
+                    // Leave a partially written last line for the next read.
+                    let complete_len = string.rfind('\n').map_or(0, |i| i + 1);
+                    position += complete_len as u64;
+
+                    for line in string[..complete_len].lines() {
+                        let record = match parser::AuditRecord::parse(line) {
+                            Ok(record) => record,
                             Err(e) => {
-                                let error_message = e.to_string();
-                                if !error_message.is_empty() {
-                                    eprintln!("ERROR: {}", error_message);
+                                eprintln!("ERROR: {}", e);
+                                continue;
+                            }
+                        };
+
+                        for audit_event in assembler.push(record) {
+                            let mut audit_log = match parser::parse_event(&audit_event) {
+                                Ok(Some(parser::ParsedEvent::Exec(audit_log))) => audit_log,
+                                Ok(Some(parser::ParsedEvent::Auth(auth_event))) => {
+                                    store_auth_event(&db_pool, &auth_event).await;
+                                    continue;
+                                }
+                                Ok(Some(parser::ParsedEvent::UserCommand(mut user_command))) => {
+                                    redactor.redact_user_command(&mut user_command);
+                                    store_user_command(&db_pool, &user_command).await;
+                                    continue;
                                 }
+                                Ok(Some(parser::ParsedEvent::File(mut file_event))) => {
+                                    redactor.redact_file_event(&mut file_event);
+                                    store_file_event(&db_pool, &file_event).await;
+                                    continue;
+                                }
+                                Ok(None) => continue,
+                                Err(e) => {
+                                    eprintln!("ERROR: {}", e);
+                                    continue;
+                                }
+                            };
+
+                            // On failure only the encrypted original is missing.
+                            if let Err(e) = redactor.redact_audit_log(&mut audit_log) {
+                                eprintln!("{e}");
                             }
+
+                            store_audit_log(
+                                &db_pool,
+                                &search_index,
+                                &live_tx,
+                                &mut detection,
+                                &audit_log,
+                            )
+                            .await;
                         }
                     }
                 }